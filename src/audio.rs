@@ -1,4 +1,5 @@
 use std::cell::RefCell;
+use std::mem;
 use std::rc::Rc;
 
 use wasm_bindgen::JsCast;
@@ -27,14 +28,36 @@ pub struct Buffer<'a> {
   pub data: &'a mut BufferData<'a>,
 }
 
+// Mono buffers only have a left channel. Channels after the first two
+// follow each other in `rest`.
 pub struct BufferData<'a> {
   left: &'a mut [f32],
-  right: &'a mut [f32]
+  right: Option<&'a mut [f32]>,
+  rest: &'a mut [f32],
+  channels: usize,
 }
 
 pub struct Sample<'a> {
   pub left: &'a mut f32,
-  pub right: &'a mut f32,
+  pub right: Option<&'a mut f32>,
+}
+
+// Recycles the storage of planar buffers. Released buffers are kept as
+// long as the pool retains less than `max_samples` samples, and buffers
+// more than `SHRINK_RATIO` times larger than requested are shrunk when
+// reacquired.
+pub struct BufferPool {
+  free: RefCell<Vec<Vec<f32>>>,
+  max_samples: usize,
+}
+
+// Zeroed planar storage, returned to its pool when dropped.
+pub struct PooledBuffer<'a> {
+  pool: &'a BufferPool,
+  samples: Vec<f32>,
+  len: usize,
+  channels: usize,
+  pub sample_rate: f32,
 }
 
 struct Processor_ {
@@ -51,16 +74,201 @@ struct Processor_ {
 }
 
 
-impl BufferData<'_> {
+impl<'a> BufferData<'a> {
+  pub fn new(left: &'a mut [f32], right: &'a mut [f32]) -> BufferData<'a> {
+    BufferData {
+      left: left,
+      right: Some(right),
+      rest: &mut [],
+      channels: 2,
+    }
+  }
+  
+  pub fn mono(samples: &'a mut [f32]) -> BufferData<'a> {
+    BufferData {
+      left: samples,
+      right: None,
+      rest: &mut [],
+      channels: 1,
+    }
+  }
+
+  // Planar `samples` of `channels` channels of the same length.
+  pub fn planar( samples: &'a mut [f32],
+                 channels: usize ) -> Result<BufferData<'a>,Error> {
+    if channels == 0 {
+      return Err(Error::InvalidConfig { name: "channels", value: 0.0 });
+    }
+    
+    if samples.len() % channels != 0 {
+      return Err(Error::PartialFrame {
+        channels: channels,
+        remainder: samples.len() % channels,
+      });
+    }
+    
+    Ok(split_planar(samples, samples.len() / channels, channels))
+  }
+
+  pub fn is_mono(&self) -> bool {
+    self.right.is_none()
+  }
+
+  pub fn len(&self) -> usize {
+    self.left.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.left.is_empty()
+  }
+
+  pub fn channels(&self) -> usize {
+    self.channels
+  }
+
+  pub fn channel(&self, channel: usize) -> Option<&[f32]> {
+    match channel {
+      0 => Some(self.left),
+      1 => self.right.as_ref().map(|r| &r[..]),
+      _ => {
+        let start = (channel - 2) * self.left.len();
+        self.rest.get(start .. start + self.left.len())
+      },
+    }
+  }
+
+  // Largest absolute sample of all channels.
+  pub fn peak(&self) -> f32 {
+    let right = self.right.as_ref().map_or(&[][..], |r| &r[..]);
+    self.left.iter().chain(right).chain(self.rest.iter())
+      .fold(0.0, |peak, x| peak.max(x.abs()))
+  }
+
+  pub fn silence(&mut self) {
+    for x in self.left.iter_mut().chain(self.rest.iter_mut()) {
+      *x = 0.0;
+    }
+    if let Some(right) = self.right.as_mut() {
+      for x in right.iter_mut() {
+        *x = 0.0;
+      }
+    }
+  }
+  
   pub fn iter_mut(&mut self) -> impl Iterator<Item=Sample> {
-    self.left.iter_mut().zip(self.right.iter_mut()).map(|(l,r)| Sample {
+    let mut right = self.right.as_mut().map(|r| r.iter_mut());
+    self.left.iter_mut().map(move |l| Sample {
       left: l,
-      right: r,
+      right: right.as_mut().and_then(|r| r.next()),
     })
   }
 }
 
 
+const SHRINK_RATIO: usize = 4;
+
+impl BufferPool {
+  pub fn new(max_samples: usize) -> BufferPool {
+    BufferPool {
+      free: RefCell::new(Vec::new()),
+      max_samples: max_samples,
+    }
+  }
+
+  // Samples currently retained by the pool.
+  pub fn retained(&self) -> usize {
+    self.free.borrow().iter().map(|v| v.capacity()).sum()
+  }
+
+  pub fn acquire( &self,
+                  len: usize,
+                  channels: usize,
+                  sample_rate: f32 ) -> PooledBuffer {
+    let size = len * channels;
+    let mut free = self.free.borrow_mut();
+    
+    // Smallest buffer that fits, or else the largest one.
+    let best = free.iter().enumerate()
+      .filter(|(_,v)| v.capacity() >= size)
+      .min_by_key(|(_,v)| v.capacity())
+      .or_else(|| free.iter().enumerate().max_by_key(|(_,v)| v.capacity()))
+      .map(|(i,_)| i);
+    
+    let mut samples = match best {
+      Some(i) => free.swap_remove(i),
+      None => Vec::new(),
+    };
+    
+    samples.clear();
+    samples.resize(size, 0.0);
+    if samples.capacity() > SHRINK_RATIO * size {
+      samples.shrink_to_fit();
+    }
+    
+    PooledBuffer {
+      pool: self,
+      samples: samples,
+      len: len,
+      channels: channels,
+      sample_rate: sample_rate,
+    }
+  }
+
+  fn release(&self, samples: Vec<f32>) {
+    if self.retained() + samples.capacity() <= self.max_samples {
+      self.free.borrow_mut().push(samples);
+    }
+  }
+}
+
+impl PooledBuffer<'_> {
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  pub fn channels(&self) -> usize {
+    self.channels
+  }
+
+  pub fn channel_mut(&mut self, channel: usize) -> &mut [f32] {
+    &mut self.samples[channel * self.len .. (channel + 1) * self.len]
+  }
+
+  pub fn data(&mut self) -> BufferData {
+    split_planar(&mut self.samples, self.len, self.channels)
+  }
+}
+
+impl Drop for PooledBuffer<'_> {
+  fn drop(&mut self) {
+    self.pool.release(mem::replace(&mut self.samples, Vec::new()));
+  }
+}
+
+
+// The first `channels` channels of `len` samples, at least one.
+fn split_planar( samples: &mut [f32],
+                 len: usize,
+                 channels: usize ) -> BufferData {
+  let (left,rest) = samples.split_at_mut(len);
+  if channels > 1 {
+    let (right,rest) = rest.split_at_mut(len);
+    BufferData {
+      left: left,
+      right: Some(right),
+      rest: &mut rest[.. (channels - 2) * len],
+      channels: channels,
+    }
+  } else {
+    BufferData::mono(left)
+  }
+}
+
+
 pub fn start_processing(args: Processor) -> Result<(),Error> {
   let processor = Rc::new(RefCell::new(None));
   
@@ -74,7 +282,7 @@ pub fn start_processing(args: Processor) -> Result<(),Error> {
   let on_rejection = {
     let processor = processor.clone();
     Closure::new(move |_| {
-      cleanup(&processor, |_| Err(Error()));
+      cleanup(&processor, |_| Err(Error::Js));
     })
   };
 
@@ -142,10 +350,10 @@ fn on_proc( processor: &mut Processor_,
   
   (processor.args.process)( Buffer {
     sample_rate: input_buffer.sample_rate(),
-    data: &mut BufferData {
-      left: &mut processor.left_buffer,
-      right: &mut processor.right_buffer,
-    }
+    data: &mut BufferData::new(
+      &mut processor.left_buffer,
+      &mut processor.right_buffer,
+    )
   })?;
   
   output_buffer.copy_to_channel(&mut processor.left_buffer, 0)?;
@@ -174,3 +382,58 @@ fn cleanup<F>(processor: &RefCell<Option<Processor_>>, f: F) where
       processor.replace(None);
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn pool_of_mixed_sizes() {
+    let pool = BufferPool::new(1000);
+    {
+      let mut buffer = pool.acquire(128, 2, 48000.0);
+      buffer.channel_mut(1)[0] = 1.0;
+      assert_eq!(buffer.data().iter_mut().count(), 128);
+    }
+    assert!(pool.retained() >= 256);
+    
+    // Reacquired zeroed, out of the pool until dropped.
+    {
+      let mut buffer = pool.acquire(128, 2, 48000.0);
+      assert_eq!(pool.retained(), 0);
+      assert!(buffer.channel_mut(1).iter().all(|&x| x == 0.0));
+    }
+    
+    // The smallest buffer that fits is taken.
+    {
+      let _large = pool.acquire(128, 2, 48000.0);
+      let _small = pool.acquire(32, 2, 48000.0);
+    }
+    let retained = pool.retained();
+    {
+      let _small = pool.acquire(32, 2, 48000.0);
+      assert!(pool.retained() >= 256, "{}", pool.retained());
+    }
+    assert_eq!(pool.retained(), retained);
+    
+    // Much smaller requests shrink the buffer they get.
+    {
+      let _large = pool.acquire(10, 1, 48000.0);
+      let _small = pool.acquire(10, 1, 48000.0);
+    }
+    assert!(pool.retained() < 100, "{}", pool.retained());
+    
+    // Buffers past the cap aren't kept.
+    { let _huge = pool.acquire(600, 2, 48000.0); }
+    assert!(pool.retained() < 100, "{}", pool.retained());
+  }
+
+  #[test]
+  fn planar_whole_frames() {
+    let mut samples = [0.0; 7];
+    assert_eq!(BufferData::planar(&mut samples[.. 6], 3).unwrap().len(), 2);
+    assert_eq!( BufferData::planar(&mut samples, 3).err(),
+                Some(Error::PartialFrame { channels: 3, remainder: 1 }) );
+  }
+}
@@ -0,0 +1,178 @@
+use std::mem;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use js_sys::Function;
+use js_sys::Object;
+use js_sys::Reflect;
+use wasm_bindgen::prelude::*;
+
+use crate::error::Error;
+use crate::spectrogram::FrameData;
+use crate::spectrogram::ProcessSummary;
+use crate::spectrogram::Spectrogram;
+
+
+#[wasm_bindgen]
+pub struct JsSpectrogram {
+  spectrogram: Spectrogram,
+  on_frame: Option<Function>,
+  frames: Arc<Mutex<Vec<FrameData>>>,
+}
+
+
+#[wasm_bindgen]
+impl JsSpectrogram {
+  #[wasm_bindgen(constructor)]
+  pub fn new( buffer_size_power: u32,
+              from_key: f32,
+              to_key: f32,
+              boost: f32 ) -> Result<JsSpectrogram,JsValue> {
+    
+    let spectrogram = Spectrogram::try_new(
+      buffer_size_power, from_key, to_key, boost ).map_err(to_js)?;
+    
+    Ok( JsSpectrogram {
+      spectrogram: spectrogram,
+      on_frame: None,
+      frames: Arc::new(Mutex::new(Vec::new())),
+    })
+  }
+
+  // Calls `f` with each frame as JSON every `every_n_ffts` FFTs, once
+  // `process` returns. `null` unregisters it.
+  pub fn on_frame( &mut self,
+                   every_n_ffts: usize,
+                   f: Option<Function> ) -> Result<(),JsValue> {
+    if f.is_none() {
+      self.spectrogram.clear_on_frame();
+      self.on_frame = None;
+      return Ok(());
+    }
+    
+    let frames = self.frames.clone();
+    self.spectrogram.on_frame(every_n_ffts, move |frame| {
+      frames.lock().unwrap().push(frame.clone());
+    }).map_err(to_js)?;
+    
+    self.on_frame = f;
+    Ok(())
+  }
+
+  pub fn set_buffer_size_power( &mut self,
+                                buffer_size_power: u32 ) -> Result<(),JsValue> {
+    self.spectrogram
+      .set_buffer_size_power(buffer_size_power)
+      .map_err(to_js)
+  }
+
+  // For pointer events: `wheel` can zoom around the key under the
+  // pointer.
+  pub fn zoom(&mut self, center_key: f32, factor: f32) -> Result<(),JsValue> {
+    self.spectrogram.zoom(center_key, factor).map_err(to_js)
+  }
+
+  pub fn pan(&mut self, delta_keys: f32) -> Result<(),JsValue> {
+    self.spectrogram.pan(delta_keys).map_err(to_js)
+  }
+
+  // Mono samples, left untouched, returning a summary of them as
+  // `{ samples, fft_frames, clipped, non_finite, peak }`. Fails once the
+  // samples are processed if the sample rate changed, the analysis having
+  // restarted.
+  pub fn process( &mut self,
+                  samples: &[f32],
+                  sample_rate: f32 ) -> Result<Object,JsValue> {
+    let summary = self.spectrogram
+      .process_planar(samples, None, sample_rate)
+      .map_err(to_js)?;
+    
+    let frames = mem::replace(&mut *self.frames.lock().unwrap(), Vec::new());
+    if let Some(f) = self.on_frame.as_ref() {
+      for frame in frames {
+        f.call1(&JsValue::NULL, &JsValue::from_str(&frame.to_json()))?;
+      }
+    }
+    
+    match self.spectrogram.take_sample_rate_change() {
+      Some((old,new)) => Err(to_js(Error::SampleRateChanged {
+        old: old,
+        new: new,
+      })),
+      None => summary_to_js(&summary),
+    }
+  }
+
+  // One RGBA row of `width` pixels.
+  pub fn draw(&mut self, width: u32) -> Result<Vec<u8>,JsValue> {
+    let mut row = vec![0; 4 * width as usize];
+    self.spectrogram
+      .draw_into_rgba(&mut row, width as usize)
+      .map_err(to_js)?;
+    Ok(row)
+  }
+
+  // Same as `draw` into a `Uint8Array` of 4 bytes per pixel, for texture
+  // uploads without a copy per row.
+  pub fn draw_row_rgba(&mut self, out: &mut [u8]) -> Result<(),JsValue> {
+    self.spectrogram.draw_row_rgba(out).map_err(to_js)
+  }
+}
+
+
+fn to_js(error: Error) -> JsValue {
+  JsValue::from_str(&error.to_string())
+}
+
+fn summary_to_js(summary: &ProcessSummary) -> Result<Object,JsValue> {
+  let object = Object::new();
+  let fields = [
+    ("samples", summary.samples as f64),
+    ("fft_frames", summary.fft_frames as f64),
+    ("clipped", summary.clipped as f64),
+    ("non_finite", summary.non_finite as f64),
+    ("peak", summary.peak as f64),
+  ];
+  
+  for (key,value) in fields.iter() {
+    Reflect::set(&object, &JsValue::from_str(key), &JsValue::from_f64(*value))?;
+  }
+  
+  Ok(object)
+}
+
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod tests {
+  use std::f32::consts::PI;
+
+  use wasm_bindgen_test::*;
+
+  use super::*;
+
+  #[wasm_bindgen_test]
+  fn round_trip() {
+    let mut spectrogram = JsSpectrogram::new(10, 1.0, 88.0, 25.0).unwrap();
+    
+    let samples = (0 .. 4096)
+      .map(|i| 0.5 * (2.0 * PI * 440.0 * i as f32 / 48000.0).sin())
+      .collect::<Vec<_>>();
+    let summary = spectrogram.process(&samples, 48000.0).unwrap();
+    let processed = Reflect::get(&summary, &JsValue::from_str("samples")).unwrap();
+    assert_eq!(processed.as_f64(), Some(4096.0));
+    
+    let row = spectrogram.draw(64).unwrap();
+    assert_eq!(row.len(), 4 * 64);
+    assert!(row.chunks(4).all(|p| p[3] == u8::max_value()));
+    assert!(row.chunks(4).any(|p| p[0] != row[0]));
+  }
+
+  #[wasm_bindgen_test]
+  fn readable_errors() {
+    let error = JsSpectrogram::new(0, 1.0, 88.0, 25.0).err().unwrap();
+    assert_eq!(error.as_string().unwrap(), "invalid buffer_size: 0");
+    
+    let error = JsSpectrogram::new(10, 60.0, 40.0, 25.0).err().unwrap();
+    assert_eq!(error.as_string().unwrap(), "invalid to: 40");
+  }
+}
@@ -1,4 +1,5 @@
 use std::cell::RefCell;
+use std::ops::Range;
 use std::rc::Rc;
 
 use wasm_bindgen::JsCast;
@@ -11,6 +12,7 @@ use web_sys::CanvasRenderingContext2d;
 use web_sys::ImageData;
 
 use crate::error::Error;
+use crate::spectrogram::Spectrogram;
 
 
 type DrawLine = Box<dyn FnMut(Line) -> Result<(),Error>>;
@@ -27,13 +29,59 @@ pub struct Line<'a> {
   pub data: &'a mut LineData<'a>,
 }
 
-pub struct LineData<'a>(&'a mut [u8]);
+pub struct LineData<'a>(&'a mut [u8], PixelFormat);
+
+// `Rgb565` packs 5 bits of red, 6 of green and 5 of blue from the most
+// significant bit, into a little-endian `u16`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PixelFormat {
+  Rgb8,
+  Rgba8,
+  Bgra8,
+  Rgb565,
+}
 
 pub struct Pixel<'a> {
   pub x: usize,
-  pub r: &'a mut u8,
-  pub g: &'a mut u8,
-  pub b: &'a mut u8,
+  data: &'a mut [u8],
+  format: PixelFormat,
+}
+
+// Marks each row with the RMS level of the input when it was drawn,
+// colored by the color map of the spectrogram from `INDICATOR_FLOOR_DB`
+// to 0 dBFS. `EdgeStrip` takes the last `width_px` pixels of the row,
+// the frame being drawn into the others. `Tint` blends the whole row
+// with that color, by `max_strength` at 0 dBFS down to nothing at the
+// floor, `max_strength` being at most `MAX_TINT`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum IndicatorStyle {
+  EdgeStrip { width_px: usize },
+  Tint { max_strength: f32 },
+}
+
+// Scrolling history of drawn frames, stored as RGBA rows indexed as a
+// ring: the row after the newest one is the oldest.
+pub struct Waterfall {
+  width: usize,
+  height: usize,
+  data: Vec<u8>,
+  row_level_indicator: Option<IndicatorStyle>,
+  indicators: Vec<RowIndicator>,
+  next_row: usize,
+  filled: usize,
+  dirty: usize,
+  rows_per_second: Option<f32>,
+  pending_rows: f64,
+  counted: Option<u64>,
+}
+
+// Pixels of a row changed by its level indicator, to leave them out of
+// exports.
+#[derive(Clone, Debug, PartialEq)]
+enum RowIndicator {
+  None,
+  Strip(Range<usize>),
+  Tint { color: [u8;3], strength: f32 },
 }
 
 struct Renderer_ {
@@ -44,17 +92,376 @@ struct Renderer_ {
 }
 
 
-impl LineData<'_> {
+impl<'a> LineData<'a> {
+  pub fn new(data: &'a mut [u8], format: PixelFormat) -> LineData<'a> {
+    LineData(data, format)
+  }
+  
+  pub fn format(&self) -> PixelFormat {
+    self.1
+  }
+  
   pub fn iter_mut(&mut self) -> impl Iterator<Item=Pixel> {
-    self.0.chunks_exact_mut(4).enumerate().map(move |(x,pixel)| {
-      if let [r,g,b,a] = pixel {
-        *a = u8::max_value();
-        Pixel { x:x, r:r, g:g, b:b }
+    let format = self.1;
+    self.0.chunks_exact_mut(format.bytes()).enumerate().map(move |(x,data)| {
+      Pixel { x: x, data: data, format: format }
+    })
+  }
+}
+
+impl Pixel<'_> {
+  // Alpha is dropped by formats without an alpha channel.
+  pub fn set(&mut self, rgb: [u8;3], alpha: u8) {
+    self.format.pack(rgb, alpha, self.data);
+  }
+}
+
+impl PixelFormat {
+  pub fn bytes(self) -> usize {
+    match self {
+      PixelFormat::Rgb565 => 2,
+      PixelFormat::Rgb8 => 3,
+      PixelFormat::Rgba8 | PixelFormat::Bgra8 => 4,
+    }
+  }
+
+  // Writes a color into the `bytes()` bytes of `out`.
+  pub fn pack(self, [r,g,b]: [u8;3], alpha: u8, out: &mut [u8]) {
+    match self {
+      PixelFormat::Rgb8 => out.copy_from_slice(&[r, g, b]),
+      PixelFormat::Rgba8 => out.copy_from_slice(&[r, g, b, alpha]),
+      PixelFormat::Bgra8 => out.copy_from_slice(&[b, g, r, alpha]),
+      PixelFormat::Rgb565 => {
+        let value = quantize(r, 5) << 11 | quantize(g, 6) << 5 | quantize(b, 5);
+        out.copy_from_slice(&value.to_le_bytes());
+      },
+    }
+  }
+}
+
+// Level the row level indicators start from.
+pub const INDICATOR_FLOOR_DB: f32 = -60.0;
+
+// Largest strength of `IndicatorStyle::Tint`, so that tinted rows can
+// still be read and untinted.
+pub const MAX_TINT: f32 = 0.5;
+
+// Nearest value of `bits` bits to an 8-bit one.
+fn quantize(value: u8, bits: u32) -> u16 {
+  let max = (1 << bits) - 1;
+  (value as u16 * max + 127) / 255
+}
+
+
+impl Waterfall {
+  pub fn new(width: usize, height: usize) -> Waterfall {
+    Waterfall {
+      width: width,
+      height: height,
+      data: vec![0; 4 * width * height],
+      row_level_indicator: None,
+      indicators: vec![RowIndicator::None; height],
+      next_row: 0,
+      filled: 0,
+      dirty: 0,
+      rows_per_second: None,
+      pending_rows: 0.0,
+      counted: None,
+    }
+  }
+
+  pub fn width(&self) -> usize {
+    self.width
+  }
+
+  pub fn height(&self) -> usize {
+    self.height
+  }
+
+  pub fn data(&self) -> &[u8] {
+    &self.data
+  }
+
+  // Row drawn by the last `push_frame`.
+  pub fn row(&self) -> usize {
+    (self.next_row + self.height - 1) % self.height.max(1)
+  }
+
+  // Number of rows drawn so far, up to `height`.
+  pub fn filled(&self) -> usize {
+    self.filled
+  }
+
+  // Filled rows from the oldest to the newest.
+  pub fn rows(&self) -> impl Iterator<Item=&[u8]> {
+    let first = self.height - self.filled + self.next_row;
+    (first .. first + self.filled).map(move |i| self.row_data(i % self.height))
+  }
+
+  // Rows of `data` changed since the last call, as at most two ranges
+  // from the oldest change to the newest, all of them after a resize.
+  // Row 0 comes first in `data`, and the lowest frequency at x = 0.
+  pub fn dirty_rows(&mut self) -> impl Iterator<Item=Range<usize>> {
+    let dirty = self.dirty;
+    self.dirty = 0;
+    
+    let first = (self.next_row + self.height - dirty) % self.height.max(1);
+    let end = first + dirty;
+    let (older,newer) = if dirty == 0 {
+      (None, None)
+    } else if end > self.height {
+      (Some(first .. self.height), Some(0 .. end - self.height))
+    } else {
+      (Some(first .. end), None)
+    };
+    
+    older.into_iter().chain(newer)
+  }
+
+  pub fn rows_per_second(&self) -> Option<f32> {
+    self.rows_per_second
+  }
+
+  // Paces the rows by the audio time analyzed instead of one per
+  // `push_frame`, starting from the next one.
+  pub fn set_rows_per_second( &mut self,
+                              rows_per_second: Option<f32> ) -> Result<(),Error> {
+    if let Some(rows) = rows_per_second {
+      if !(rows > 0.0 && rows.is_finite()) {
+        return Err(Error::InvalidConfig { name: "rows_per_second", value: rows });
       }
-      else {
-        panic!()
+    }
+    
+    self.rows_per_second = rows_per_second;
+    self.pending_rows = 0.0;
+    self.counted = None;
+    Ok(())
+  }
+
+  pub fn row_level_indicator(&self) -> Option<IndicatorStyle> {
+    self.row_level_indicator
+  }
+
+  // Indicates the level of the rows drawn from the next one.
+  pub fn set_row_level_indicator( &mut self,
+                                  style: Option<IndicatorStyle>
+                                ) -> Result<(),Error> {
+    match style {
+      Some(IndicatorStyle::EdgeStrip { width_px: 0 }) =>
+        return Err(Error::InvalidConfig { name: "width_px", value: 0.0 }),
+      Some(IndicatorStyle::Tint { max_strength })
+        if !(max_strength > 0.0 && max_strength <= MAX_TINT) =>
+        return Err(Error::InvalidConfig { name: "max_strength", value: max_strength }),
+      _ => (),
+    }
+    
+    self.row_level_indicator = style;
+    Ok(())
+  }
+
+  // Draws the frame accumulated by `spectrogram` into new rows, replacing
+  // the oldest ones, and returns how many. Nothing is drawn if there is
+  // no frame. Without a pace there is a row per call, otherwise the rows
+  // due since the last one, the FFTs in between blending as the
+  // spectrogram accumulates them, and the row repeated if several are
+  // due at once. Rows falling due while there is no frame, when the hop
+  // is longer than the audio between calls, are drawn with the next one,
+  // as a burst of up to `height` repeats, so that every row stands for
+  // the same span of audio.
+  pub fn push_frame(&mut self, spectrogram: &mut Spectrogram) -> Result<usize,Error> {
+    let due = match self.rows_per_second {
+      Some(rows_per_second) => self.pace(spectrogram, rows_per_second),
+      None => 1,
+    };
+    
+    if due == 0 || self.height == 0 || spectrogram.frame().is_none() {
+      return Ok(0);
+    }
+    
+    // The remainder carries over so that the pace doesn't drift.
+    if self.rows_per_second.is_some() {
+      self.pending_rows -= due as f64;
+    }
+    let rows = due.min(self.height);
+
+    let len = 4 * self.width;
+    let row = self.next_row;
+    let data = &mut self.data[row * len .. (row + 1) * len];
+    
+    let strip = match self.row_level_indicator {
+      Some(IndicatorStyle::EdgeStrip { width_px }) => width_px.min(self.width),
+      _ => 0,
+    };
+    let width = self.width - strip;
+    
+    spectrogram.draw_frame( Line {
+      len: width,
+      data: &mut LineData::new(&mut data[.. 4 * width], PixelFormat::Rgba8),
+    })?;
+    
+    let rms_db = spectrogram.levels().rms_db;
+    let level = (1.0 - rms_db / INDICATOR_FLOOR_DB).max(0.0).min(1.0);
+    let color = spectrogram.color_map().color(level);
+    
+    self.indicators[row] = match self.row_level_indicator {
+      None => RowIndicator::None,
+      Some(IndicatorStyle::EdgeStrip {..}) => {
+        for pixel in data[4 * width ..].chunks_exact_mut(4) {
+          pixel.copy_from_slice(&[color[0], color[1], color[2], u8::max_value()]);
+        }
+        RowIndicator::Strip(width .. self.width)
+      },
+      Some(IndicatorStyle::Tint { max_strength }) => {
+        let strength = max_strength * level;
+        for pixel in data.chunks_exact_mut(4) {
+          for (c,&t) in pixel.iter_mut().zip(color.iter()) {
+            *c = (*c as f32 + strength * (t as f32 - *c as f32)).round() as u8;
+          }
+        }
+        RowIndicator::Tint { color: color, strength: strength }
+      },
+    };
+    
+    for i in 1 .. rows {
+      let copy = (row + i) % self.height;
+      self.data.copy_within(row * len .. (row + 1) * len, copy * len);
+      self.indicators[copy] = self.indicators[row].clone();
+    }
+    
+    self.next_row = (row + rows) % self.height;
+    self.filled = (self.filled + rows).min(self.height);
+    self.dirty = (self.dirty + rows).min(self.height);
+    
+    Ok(rows)
+  }
+
+  // Keeps the newest rows that fit, cropping or padding them with
+  // transparent pixels to the new width.
+  pub fn resize(&mut self, width: usize, height: usize) {
+    let mut resized = Waterfall::new(width, height);
+    let keep = self.filled.min(height);
+    let len = 4 * width.min(self.width);
+    
+    let first = self.height - self.filled + self.next_row;
+    for i in 0 .. keep {
+      let old = (first + self.filled - keep + i) % self.height;
+      let start = 4 * width * i;
+      resized.data[start .. start + len].copy_from_slice(&self.row_data(old)[.. len]);
+      resized.indicators[i] = match &self.indicators[old] {
+        RowIndicator::Strip(strip) =>
+          RowIndicator::Strip(strip.start.min(width) .. strip.end.min(width)),
+        indicator => indicator.clone(),
+      };
+    }
+    
+    resized.filled = keep;
+    resized.next_row = if height > 0 { keep % height } else { 0 };
+    resized.dirty = height;
+    resized.row_level_indicator = self.row_level_indicator;
+    resized.rows_per_second = self.rows_per_second;
+    resized.pending_rows = self.pending_rows;
+    resized.counted = self.counted;
+    
+    *self = resized;
+  }
+
+  // Filled rows from the oldest to the newest, streamed to the encoder.
+  // Fails with `Error::Encode` until a row is drawn, or without a width,
+  // images having at least a pixel.
+  #[cfg(feature = "png")]
+  pub fn to_png(&self) -> Result<Vec<u8>,Error> {
+    self.encode_png(true)
+  }
+
+  // Same as `to_png` with the strips of the level indicator transparent
+  // and the tint taken out again, up to rounding.
+  #[cfg(feature = "png")]
+  pub fn to_png_without_indicator(&self) -> Result<Vec<u8>,Error> {
+    self.encode_png(false)
+  }
+
+  #[cfg(feature = "png")]
+  fn encode_png(&self, indicator: bool) -> Result<Vec<u8>,Error> {
+    use std::io::Write;
+    
+    if self.width == 0 || self.filled == 0 {
+      return Err(Error::Encode);
+    }
+    
+    let mut png = Vec::new();
+    {
+      let mut encoder = png::Encoder::new( &mut png,
+                                           self.width as u32,
+                                           self.filled as u32 );
+      encoder.set_color(png::ColorType::RGBA);
+      encoder.set_depth(png::BitDepth::Eight);
+      
+      let mut writer = encoder.write_header()?;
+      let mut stream = writer.stream_writer();
+      let mut untinted = Vec::new();
+      let first = self.height - self.filled + self.next_row;
+      for i in first .. first + self.filled {
+        let row = self.row_data(i % self.height);
+        match &self.indicators[i % self.height] {
+          RowIndicator::None => stream.write_all(row)?,
+          _ if indicator => stream.write_all(row)?,
+          other => {
+            untinted.clear();
+            untinted.extend_from_slice(row);
+            remove_indicator(other, &mut untinted);
+            stream.write_all(&untinted)?;
+          },
+        }
       }
-    })
+      stream.flush()?;
+    }
+    
+    Ok(png)
+  }
+
+  // Whole rows due for the samples analyzed by `spectrogram` since the
+  // last call.
+  fn pace(&mut self, spectrogram: &Spectrogram, rows_per_second: f32) -> usize {
+    let count = spectrogram.sample_count();
+    let elapsed = match self.counted {
+      // The count starts over when the spectrogram is reset.
+      Some(counted) if count >= counted => count - counted,
+      Some(_) => count,
+      None => 0,
+    };
+    self.counted = Some(count);
+    
+    if let Some(sample_rate) = spectrogram.sample_rate() {
+      self.pending_rows += elapsed as f64 * rows_per_second as f64 / sample_rate as f64;
+    }
+    
+    self.pending_rows.max(0.0) as usize
+  }
+
+  fn row_data(&self, row: usize) -> &[u8] {
+    let len = 4 * self.width;
+    &self.data[row * len .. (row + 1) * len]
+  }
+}
+
+// Undoes the level indicator of an RGBA row.
+#[cfg(feature = "png")]
+fn remove_indicator(indicator: &RowIndicator, row: &mut [u8]) {
+  match indicator {
+    RowIndicator::None => (),
+    RowIndicator::Strip(strip) => {
+      for x in row[4 * strip.start .. 4 * strip.end].iter_mut() {
+        *x = 0;
+      }
+    },
+    RowIndicator::Tint { color, strength } => {
+      for pixel in row.chunks_exact_mut(4) {
+        for (c,&t) in pixel.iter_mut().zip(color.iter()) {
+          let v = (*c as f32 - strength * t as f32) / (1.0 - strength);
+          *c = v.round().max(0.0).min(255.0) as u8;
+        }
+      }
+    },
   }
 }
 
@@ -110,7 +517,7 @@ fn on_frame(renderer: &mut Renderer_) -> Result<(),Error> {
   
   (renderer.args.draw_frame)( Line {
     len: width,
-    data: &mut LineData(&mut renderer.data),
+    data: &mut LineData::new(&mut renderer.data, PixelFormat::Rgba8),
   })?;
   
   let line = ImageData::new_with_u8_clamped_array(
@@ -146,3 +553,390 @@ fn resize(canvas: &HtmlCanvasElement, resolution: f32) -> (usize,usize) {
   
   (width as usize, height as usize)
 }
+
+
+#[cfg(test)]
+mod tests {
+  use std::f32::consts::PI;
+
+  use crate::filter::Decimation;
+  use crate::spectrogram::SpectrogramBuilder;
+
+  use super::*;
+
+  fn tone(spectrogram: &mut Spectrogram, hz: f32, len: usize) {
+    let samples = (0 .. len).map(|i| {
+      0.5 * (2.0 * PI * hz * i as f32 / 48000.0).sin()
+    }).collect::<Vec<_>>();
+    spectrogram.process_planar(&samples, None, 48000.0).unwrap();
+  }
+
+  // The row `push_frame` will draw without a level indicator.
+  fn peek(spectrogram: &Spectrogram, width: usize) -> Vec<u8> {
+    let mut rgba = vec![0; 4 * width];
+    spectrogram.draw_frame_peek( Line {
+      len: width,
+      data: &mut LineData::new(&mut rgba, PixelFormat::Rgba8),
+    }).unwrap();
+    rgba
+  }
+
+  // Pushes a row for each tone, returning the rows drawn.
+  fn push_tones( waterfall: &mut Waterfall,
+                 spectrogram: &mut Spectrogram,
+                 tones: &[f32] ) -> Vec<Vec<u8>> {
+    tones.iter().map(|&hz| {
+      tone(spectrogram, hz, 4096);
+      let row = peek(spectrogram, waterfall.width());
+      assert_eq!(waterfall.push_frame(spectrogram).unwrap(), 1);
+      row
+    }).collect()
+  }
+
+  fn rows(waterfall: &Waterfall) -> Vec<Vec<u8>> {
+    waterfall.rows().map(|row| row.to_vec()).collect()
+  }
+
+  const TONES: [f32; 7] = [100.0, 200.0, 400.0, 800.0, 1600.0, 3200.0, 6400.0];
+
+  #[test]
+  fn rows_after_wrapping_around() {
+    let mut spectrogram = SpectrogramBuilder::new().build().unwrap();
+    let mut waterfall = Waterfall::new(8, 3);
+    assert_eq!(waterfall.rows().count(), 0);
+    
+    let drawn = push_tones(&mut waterfall, &mut spectrogram, &TONES[.. 2]);
+    assert_eq!(waterfall.filled(), 2);
+    assert_eq!(waterfall.row(), 1);
+    assert_eq!(rows(&waterfall), drawn);
+    
+    let drawn = push_tones(&mut waterfall, &mut spectrogram, &TONES);
+    for (i,row) in drawn.iter().enumerate().skip(1) {
+      assert!(row != &drawn[i - 1], "{}", i);
+    }
+    assert_eq!(waterfall.filled(), 3);
+    assert_eq!(waterfall.row(), 8 % 3);
+    assert_eq!(rows(&waterfall), &drawn[4 ..]);
+    assert_eq!(&waterfall.data()[4 * 8 * 2 .. 4 * 8 * 3], &drawn[6][..]);
+  }
+
+  #[test]
+  fn resize_keeps_the_newest_rows() {
+    let mut spectrogram = SpectrogramBuilder::new().build().unwrap();
+    let mut waterfall = Waterfall::new(8, 4);
+    let drawn = push_tones(&mut waterfall, &mut spectrogram, &TONES[.. 6]);
+    
+    waterfall.resize(8, 2);
+    assert_eq!(rows(&waterfall), &drawn[4 ..]);
+    
+    waterfall.resize(8, 5);
+    assert_eq!(waterfall.filled(), 2);
+    assert_eq!(rows(&waterfall), &drawn[4 ..]);
+    let newest = push_tones(&mut waterfall, &mut spectrogram, &TONES[6 ..]);
+    assert_eq!(waterfall.row(), 2);
+    assert_eq!(rows(&waterfall), [&drawn[4 ..], &newest[..]].concat());
+    
+    // Cropped then padded with transparent pixels.
+    waterfall.resize(3, 5);
+    waterfall.resize(4, 5);
+    let rows = rows(&waterfall);
+    assert_eq!(&rows[2][.. 12], &newest[0][.. 12]);
+    assert_eq!(&rows[2][12 ..], &[0; 4]);
+    
+    waterfall.resize(0, 0);
+    assert_eq!(waterfall.rows().count(), 0);
+  }
+
+  fn dirty(waterfall: &mut Waterfall) -> Vec<Range<usize>> {
+    waterfall.dirty_rows().collect()
+  }
+
+  #[test]
+  fn dirty_rows_since_the_last_call() {
+    let mut spectrogram = SpectrogramBuilder::new().build().unwrap();
+    let mut waterfall = Waterfall::new(8, 4);
+    assert_eq!(dirty(&mut waterfall), []);
+    
+    push_tones(&mut waterfall, &mut spectrogram, &TONES[.. 2]);
+    assert_eq!(dirty(&mut waterfall), [0 .. 2]);
+    assert_eq!(dirty(&mut waterfall), []);
+    
+    push_tones(&mut waterfall, &mut spectrogram, &TONES[.. 3]);
+    assert_eq!(dirty(&mut waterfall), [2 .. 4, 0 .. 1]);
+    
+    push_tones(&mut waterfall, &mut spectrogram, &TONES);
+    assert_eq!(dirty(&mut waterfall), [0 .. 4]);
+    
+    push_tones(&mut waterfall, &mut spectrogram, &TONES[.. 1]);
+    waterfall.resize(8, 3);
+    assert_eq!(dirty(&mut waterfall), [0 .. 3]);
+    assert_eq!(dirty(&mut waterfall), []);
+  }
+
+  fn packed(format: PixelFormat, rgb: [u8;3], alpha: u8) -> Vec<u8> {
+    let mut out = vec![0; format.bytes()];
+    format.pack(rgb, alpha, &mut out);
+    out
+  }
+
+  fn rgb565(rgb: [u8;3]) -> u16 {
+    let out = packed(PixelFormat::Rgb565, rgb, 0);
+    u16::from_le_bytes([out[0], out[1]])
+  }
+
+  #[test]
+  fn pixel_formats() {
+    let color = [200, 100, 7];
+    assert_eq!(packed(PixelFormat::Rgb8, color, 9), [200, 100, 7]);
+    assert_eq!(packed(PixelFormat::Rgba8, color, 9), [200, 100, 7, 9]);
+    assert_eq!(packed(PixelFormat::Bgra8, color, 9), [7, 100, 200, 9]);
+    
+    // 200 * 31 / 255 = 24.3, 100 * 63 / 255 = 24.7, 7 * 31 / 255 = 0.85.
+    assert_eq!(rgb565(color), 24 << 11 | 25 << 5 | 1);
+    // 128 * 31 / 255 = 15.6, 128 * 63 / 255 = 31.6.
+    assert_eq!(rgb565([128, 128, 128]), 16 << 11 | 32 << 5 | 16);
+    assert_eq!(rgb565([255, 255, 255]), 0xffff);
+    assert_eq!(rgb565([0, 0, 0]), 0);
+    
+    let mut rgb = vec![0; 2 * 3];
+    for mut pixel in LineData::new(&mut rgb, PixelFormat::Rgb8).iter_mut() {
+      pixel.set(color, 9);
+    }
+    assert_eq!(rgb, [200, 100, 7, 200, 100, 7]);
+  }
+
+  #[test]
+  fn same_frame_in_every_format() {
+    let mut spectrogram = SpectrogramBuilder::new().build().unwrap();
+    tone(&mut spectrogram, 440.0, 4096);
+    let rgba = peek(&spectrogram, 10);
+    
+    let formats = [PixelFormat::Rgb8, PixelFormat::Bgra8, PixelFormat::Rgb565];
+    for &format in formats.iter() {
+      let mut data = vec![0; 10 * format.bytes()];
+      spectrogram.draw_frame_peek( Line {
+        len: 10,
+        data: &mut LineData::new(&mut data, format),
+      }).unwrap();
+      
+      let expected = rgba.chunks(4).flat_map(|p| {
+        packed(format, [p[0], p[1], p[2]], p[3])
+      }).collect::<Vec<_>>();
+      assert_eq!(data, expected, "{:?}", format);
+    }
+  }
+
+  // Rows drawn at 7.3 rows per second over 10 minutes of audio at 8 kHz,
+  // fed in chunks of 133 samples, and the largest burst.
+  fn paced(hop: usize) -> (Waterfall, usize, usize) {
+    let mut spectrogram = SpectrogramBuilder::new()
+      .buffer_size(256)
+      .decimation(Decimation::None)
+      .hop(hop)
+      .build().unwrap();
+    let mut waterfall = Waterfall::new(2, 100);
+    waterfall.set_rows_per_second(Some(7.3)).unwrap();
+    
+    let chunk = [0.1; 133];
+    let (mut total, mut burst) = (0, 0);
+    for _ in 0 .. 10 * 60 * 8000 / chunk.len() {
+      spectrogram.process_planar(&chunk, None, 8000.0).unwrap();
+      let rows = waterfall.push_frame(&mut spectrogram).unwrap();
+      total += rows;
+      burst = burst.max(rows);
+    }
+    (waterfall, total, burst)
+  }
+
+  #[test]
+  fn pace_does_not_drift() {
+    // The first call only starts counting.
+    let seconds = (10 * 60 * 8000 / 133 - 1) as f64 * 133.0 / 8000.0;
+    
+    for &hop in [64, 4096].iter() {
+      let (waterfall, total, burst) = paced(hop);
+      let committed = total as f64 + waterfall.pending_rows;
+      let expected = 7.3_f32 as f64 * seconds;
+      assert!((committed - expected).abs() < 1e-6, "{} {}", hop, committed);
+      
+      // A row per call at most unless there are calls without a frame.
+      assert_eq!(burst, if hop == 64 { 1 } else { 4 }, "{}", hop);
+    }
+  }
+
+  // Color of the level indicator of the last pushed row.
+  fn level_color(spectrogram: &Spectrogram) -> [u8;4] {
+    let rms_db = spectrogram.levels().rms_db;
+    let level = (1.0 - rms_db / INDICATOR_FLOOR_DB).max(0.0).min(1.0);
+    let [r,g,b] = spectrogram.color_map().color(level);
+    [r, g, b, u8::max_value()]
+  }
+
+  #[test]
+  fn edge_strip() {
+    let mut spectrogram = SpectrogramBuilder::new().build().unwrap();
+    let mut waterfall = Waterfall::new(6, 3);
+    let strip = IndicatorStyle::EdgeStrip { width_px: 2 };
+    waterfall.set_row_level_indicator(Some(strip)).unwrap();
+    
+    tone(&mut spectrogram, 440.0, 4096);
+    let frame = peek(&spectrogram, 4);
+    waterfall.push_frame(&mut spectrogram).unwrap();
+    let row = waterfall.rows().last().unwrap().to_vec();
+    assert_eq!(&row[.. 16], &frame[..]);
+    assert_eq!(&row[16 .. 20], &level_color(&spectrogram));
+    assert_eq!(&row[20 ..], &level_color(&spectrogram));
+    assert_eq!(waterfall.indicators[0], RowIndicator::Strip(4 .. 6));
+    
+    // Wider than the row, the strip is the whole row.
+    let strip = IndicatorStyle::EdgeStrip { width_px: 10 };
+    waterfall.set_row_level_indicator(Some(strip)).unwrap();
+    tone(&mut spectrogram, 440.0, 4096);
+    waterfall.push_frame(&mut spectrogram).unwrap();
+    let row = waterfall.rows().last().unwrap().to_vec();
+    assert!(row.chunks(4).all(|p| p == &level_color(&spectrogram)[..]));
+    assert_eq!(waterfall.indicators[1], RowIndicator::Strip(0 .. 6));
+    
+    let strip = IndicatorStyle::EdgeStrip { width_px: 0 };
+    assert!(waterfall.set_row_level_indicator(Some(strip)).is_err());
+  }
+
+  #[test]
+  fn tint_at_most_max_tint() {
+    let mut spectrogram = SpectrogramBuilder::new().build().unwrap();
+    let mut waterfall = Waterfall::new(6, 3);
+    for &strength in [0.0, MAX_TINT + 0.01, std::f32::NAN].iter() {
+      let tint = IndicatorStyle::Tint { max_strength: strength };
+      assert!(waterfall.set_row_level_indicator(Some(tint)).is_err());
+    }
+    let tint = IndicatorStyle::Tint { max_strength: MAX_TINT };
+    waterfall.set_row_level_indicator(Some(tint)).unwrap();
+    
+    // A full scale square wave, at 0 dBFS.
+    let samples = (0 .. 4096)
+      .map(|i| if i / 50 % 2 == 0 { 1.0 } else { -1.0 })
+      .collect::<Vec<_>>();
+    spectrogram.process_planar(&samples, None, 48000.0).unwrap();
+    let frame = peek(&spectrogram, 6);
+    waterfall.push_frame(&mut spectrogram).unwrap();
+    let color = level_color(&spectrogram);
+    
+    let strength = match waterfall.indicators[0] {
+      RowIndicator::Tint { strength, .. } => strength,
+      ref indicator => panic!("{:?}", indicator),
+    };
+    assert!(strength > 0.5 * MAX_TINT && strength <= MAX_TINT, "{}", strength);
+    
+    let row = waterfall.rows().last().unwrap().to_vec();
+    for (tinted,plain) in row.chunks(4).zip(frame.chunks(4)) {
+      for i in 0 .. 3 {
+        let (t, p, c) = (tinted[i] as f32, plain[i] as f32, color[i] as f32);
+        assert!((t - (p + strength * (c - p))).abs() <= 0.5, "{:?}", row);
+      }
+      assert_eq!(tinted[3], plain[3]);
+    }
+  }
+}
+
+
+#[cfg(all(test, feature = "png"))]
+mod png_tests {
+  use std::f32::consts::PI;
+
+  use crate::spectrogram::SpectrogramBuilder;
+
+  use super::*;
+
+  fn tone(spectrogram: &mut Spectrogram, hz: f32) {
+    let samples = (0 .. 4096).map(|i| {
+      0.5 * (2.0 * PI * hz * i as f32 / 48000.0).sin()
+    }).collect::<Vec<_>>();
+    spectrogram.process_planar(&samples, None, 48000.0).unwrap();
+  }
+
+  fn push_tone( waterfall: &mut Waterfall,
+                spectrogram: &mut Spectrogram,
+                hz: f32 ) {
+    tone(spectrogram, hz);
+    waterfall.push_frame(spectrogram).unwrap();
+  }
+
+  // Width, height and RGBA pixels of a PNG image.
+  fn decode(image: &[u8]) -> (u32, u32, Vec<u8>) {
+    let (info, mut reader) = png::Decoder::new(image).read_info().unwrap();
+    let mut rgba = vec![0; info.buffer_size()];
+    reader.next_frame(&mut rgba).unwrap();
+    (info.width, info.height, rgba)
+  }
+
+  fn rows(waterfall: &Waterfall) -> Vec<u8> {
+    waterfall.rows().flat_map(|row| row.iter().cloned()).collect()
+  }
+
+  #[test]
+  fn rows_in_order() {
+    let mut spectrogram = SpectrogramBuilder::new().build().unwrap();
+    let mut waterfall = Waterfall::new(4, 3);
+    assert_eq!(waterfall.to_png(), Err(Error::Encode));
+    
+    push_tone(&mut waterfall, &mut spectrogram, 200.0);
+    push_tone(&mut waterfall, &mut spectrogram, 800.0);
+    let png = waterfall.to_png().unwrap();
+    assert_eq!(decode(&png), (4, 2, rows(&waterfall)));
+    
+    for &hz in [3200.0, 100.0, 1600.0].iter() {
+      push_tone(&mut waterfall, &mut spectrogram, hz);
+    }
+    assert!(rows(&waterfall) != waterfall.data());
+    let png = waterfall.to_png().unwrap();
+    assert_eq!(decode(&png), (4, 3, rows(&waterfall)));
+    
+    assert_eq!(Waterfall::new(0, 3).to_png(), Err(Error::Encode));
+  }
+
+  #[test]
+  fn without_indicator() {
+    let mut spectrogram = SpectrogramBuilder::new().build().unwrap();
+    let mut waterfall = Waterfall::new(6, 2);
+    let strip = IndicatorStyle::EdgeStrip { width_px: 2 };
+    waterfall.set_row_level_indicator(Some(strip)).unwrap();
+    push_tone(&mut waterfall, &mut spectrogram, 440.0);
+    
+    let (_, _, with) = decode(&waterfall.to_png().unwrap());
+    let untinted = waterfall.to_png_without_indicator().unwrap();
+    let (_, _, without) = decode(&untinted);
+    assert_eq!(with, rows(&waterfall));
+    assert_eq!(&without[.. 16], &with[.. 16]);
+    assert_eq!(&without[16 ..], &[0; 8]);
+    
+    let tint = IndicatorStyle::Tint { max_strength: MAX_TINT };
+    waterfall.set_row_level_indicator(Some(tint)).unwrap();
+    tone(&mut spectrogram, 440.0);
+    let mut plain = vec![0; 4 * 6];
+    spectrogram.draw_frame_peek( Line {
+      len: 6,
+      data: &mut LineData::new(&mut plain, PixelFormat::Rgba8),
+    }).unwrap();
+    waterfall.push_frame(&mut spectrogram).unwrap();
+    
+    let untinted = waterfall.to_png_without_indicator().unwrap();
+    let (_, _, without) = decode(&untinted);
+    assert!(rows(&waterfall)[4 * 6 ..] != plain[..]);
+    for (&u,&p) in without[4 * 6 ..].iter().zip(plain.iter()) {
+      assert!((u as i32 - p as i32).abs() <= 1, "{:?} {:?}", without, plain);
+    }
+  }
+
+  #[test]
+  fn untint() {
+    // 10 + 0.5 * (255 - 10) = 132.5, 200 - 0.5 * 200 = 100, 50 + 0.5 * 50 = 75.
+    let tint = RowIndicator::Tint { color: [255, 0, 100], strength: 0.5 };
+    let mut row = [133, 100, 75, 255];
+    remove_indicator(&tint, &mut row);
+    assert_eq!(row, [11, 200, 50, 255]);
+    
+    let mut row = [1; 12];
+    remove_indicator(&RowIndicator::Strip(1 .. 2), &mut row);
+    assert_eq!(row, [1, 1, 1, 1, 0, 0, 0, 0, 1, 1, 1, 1]);
+  }
+}
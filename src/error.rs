@@ -1,3 +1,4 @@
+use std::fmt;
 use std::option::NoneError;
 
 use wasm_bindgen::JsValue;
@@ -7,18 +8,67 @@ use web_sys::Element;
 use js_sys::Object;
 
 
-#[derive(Debug, PartialEq, Eq)]
-pub struct Error();
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Error {
+  Js,
+  InvalidConfig { name: &'static str, value: f32 },
+  BufferSize { expected: usize, actual: usize },
+  ChannelLengthMismatch { left: usize, right: usize },
+  PartialFrame { channels: usize, remainder: usize },
+  TooFewChannels { required: usize, actual: usize },
+  SampleRateChanged { old: f32, new: f32 },
+  Encode,
+  Wav(&'static str),
+}
+
+
+impl fmt::Display for Error {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self {
+      Error::Js =>
+        write!(f, "JavaScript call failed"),
+      Error::InvalidConfig { name, value } =>
+        write!(f, "invalid {}: {}", name, value),
+      Error::BufferSize { expected, actual } =>
+        write!(f, "buffer of {} elements, expected {}", actual, expected),
+      Error::ChannelLengthMismatch { left, right } =>
+        write!(f, "right channel of {} samples, left of {}", right, left),
+      Error::PartialFrame { channels, remainder } =>
+        write!( f, "{} samples past the last whole frame of {} channels",
+                remainder, channels ),
+      Error::TooFewChannels { required, actual } =>
+        write!(f, "{} channels, {} required", actual, required),
+      Error::SampleRateChanged { old, new } =>
+        write!(f, "sample rate changed from {} Hz to {} Hz", old, new),
+      Error::Encode =>
+        write!(f, "image encoding failed"),
+      Error::Wav(reason) =>
+        write!(f, "invalid WAV file: {}", reason),
+    }
+  }
+}
+
+impl std::error::Error for Error {}
 
 impl From<NoneError> for Error {
-  fn from(_: NoneError) -> Error { Error() }
+  fn from(_: NoneError) -> Error { Error::Js }
 }
 impl From<Element> for Error {
-  fn from(_: Element) -> Error { Error() }
+  fn from(_: Element) -> Error { Error::Js }
 }
 impl From<JsValue> for Error {
-  fn from(_: JsValue) -> Error { Error() }
+  fn from(_: JsValue) -> Error { Error::Js }
 }
 impl From<Object> for Error {
-  fn from(_: Object) -> Error { Error() }
+  fn from(_: Object) -> Error { Error::Js }
+}
+
+#[cfg(feature = "png")]
+impl From<png::EncodingError> for Error {
+  fn from(_: png::EncodingError) -> Error { Error::Encode }
+}
+#[cfg(feature = "png")]
+impl From<std::io::Error> for Error {
+  fn from(_: std::io::Error) -> Error { Error::Encode }
 }
@@ -0,0 +1,250 @@
+use std::f32::consts::PI;
+
+use crate::window::Window;
+
+
+// Decimation of the input before the FFT, trading the top of the
+// spectrum for resolution at the bottom.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Decimation {
+  None,
+  Half,
+  Quarter,
+  Eighth,
+  Sixteenth,
+}
+
+// Low-pass filters the input and keeps one sample out of `factor`.
+pub struct Decimator {
+  factor: usize,
+  taps: Vec<f32>,
+  history: Vec<f32>,
+  pos: usize,
+  phase: usize,
+}
+
+// One-pole high-pass removing DC offsets.
+pub struct HighPass {
+  cutoff_hz: f32,
+  coefficient: f32,
+  previous_in: f32,
+  previous_out: f32,
+}
+
+// `y[n] = x[n] - coefficient * x[n-1]`, lifting the high end of the
+// spectrum.
+pub struct PreEmphasis {
+  coefficient: f32,
+  previous: f32,
+}
+
+
+impl Decimation {
+  pub fn factor(self) -> usize {
+    match self {
+      Decimation::None      => 1,
+      Decimation::Half      => 2,
+      Decimation::Quarter   => 4,
+      Decimation::Eighth    => 8,
+      Decimation::Sixteenth => 16,
+    }
+  }
+
+  // Delay of the filter in input samples, half its length.
+  pub fn delay(self) -> usize {
+    self.taps().len() / 2
+  }
+
+  // Windowed sinc with its cutoff a bit below the decimated Nyquist
+  // frequency, normalized to a unit gain at DC.
+  fn taps(self) -> Vec<f32> {
+    let factor = self.factor();
+    if factor == 1 {
+      return vec![1.0];
+    }
+
+    let len = 8 * factor + 1;
+    let center = (len / 2) as f32;
+    let cutoff = 0.8 * 0.5 / factor as f32;
+    
+    let mut taps: Vec<f32> = Window::Blackman.coefficients(len).iter()
+      .enumerate()
+      .map(|(i,w)| {
+        let x = i as f32 - center;
+        let sinc = if x == 0.0 {
+          2.0 * cutoff
+        } else {
+          (2.0 * PI * cutoff * x).sin() / (PI * x)
+        };
+        w * sinc
+      })
+      .collect();
+
+    let sum: f32 = taps.iter().sum();
+    for tap in taps.iter_mut() {
+      *tap /= sum;
+    }
+
+    taps
+  }
+}
+
+impl Decimator {
+  pub fn new(decimation: Decimation) -> Decimator {
+    let taps = decimation.taps();
+    
+    Decimator {
+      factor: decimation.factor(),
+      history: vec![0.0; taps.len()],
+      taps: taps,
+      pos: 0,
+      phase: 0,
+    }
+  }
+
+  // The filtered sample, once every `factor` calls.
+  pub fn push(&mut self, value: f32) -> Option<f32> {
+    let len = self.history.len();
+    self.history[self.pos] = value;
+    self.pos = (self.pos + 1) % len;
+    
+    self.phase += 1;
+    if self.phase < self.factor {
+      return None;
+    }
+    self.phase = 0;
+
+    let pos = self.pos;
+    Some( self.taps.iter().enumerate()
+          .map(|(i,tap)| tap * self.history[(pos + i) % len])
+          .sum() )
+  }
+
+  pub fn reset(&mut self) {
+    for x in self.history.iter_mut() {
+      *x = 0.0;
+    }
+    self.pos = 0;
+    self.phase = 0;
+  }
+}
+
+impl PreEmphasis {
+  pub fn new(coefficient: f32) -> PreEmphasis {
+    PreEmphasis {
+      coefficient: coefficient,
+      previous: 0.0,
+    }
+  }
+
+  pub fn coefficient(&self) -> f32 {
+    self.coefficient
+  }
+
+  pub fn push(&mut self, value: f32) -> f32 {
+    let y = value - self.coefficient * self.previous;
+    self.previous = value;
+    y
+  }
+
+  pub fn reset(&mut self) {
+    self.previous = 0.0;
+  }
+}
+
+impl HighPass {
+  pub fn new(cutoff_hz: f32, sample_rate: f32) -> HighPass {
+    let mut high_pass = HighPass {
+      cutoff_hz: cutoff_hz,
+      coefficient: 0.0,
+      previous_in: 0.0,
+      previous_out: 0.0,
+    };
+    high_pass.set_sample_rate(sample_rate);
+    high_pass
+  }
+
+  pub fn cutoff_hz(&self) -> f32 {
+    self.cutoff_hz
+  }
+
+  pub fn set_sample_rate(&mut self, sample_rate: f32) {
+    self.coefficient = (-2.0 * PI * self.cutoff_hz / sample_rate).exp();
+  }
+
+  pub fn push(&mut self, value: f32) -> f32 {
+    let y = self.coefficient * (self.previous_out + value - self.previous_in);
+    self.previous_in = value;
+    self.previous_out = y;
+    y
+  }
+
+  pub fn reset(&mut self) {
+    self.previous_in = 0.0;
+    self.previous_out = 0.0;
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use crate::spectrogram::SpectrogramBuilder;
+
+  use super::*;
+
+  #[test]
+  fn pre_emphasis_across_buffers() {
+    let samples = (0 .. 1000)
+      .map(|i| (i as f32 * 0.07).sin() + 0.3 * (i as f32 * 1.3).cos())
+      .collect::<Vec<_>>();
+    let expected = (0 .. samples.len()).map(|n| {
+      let previous = if n > 0 { samples[n - 1] } else { 0.0 };
+      samples[n] - 0.97 * previous
+    }).collect::<Vec<_>>();
+    
+    let mut pre_emphasis = PreEmphasis::new(0.97);
+    let mut output = Vec::new();
+    let mut start = 0;
+    for &len in [1, 127, 128, 300, 444].iter() {
+      let buffer = &samples[start .. start + len];
+      output.extend(buffer.iter().map(|&x| pre_emphasis.push(x)));
+      start += len;
+    }
+    assert_eq!(output, expected);
+    
+    pre_emphasis.reset();
+    assert_eq!(pre_emphasis.push(samples[0]), expected[0]);
+  }
+
+  fn low_bins(high_pass: Option<f32>) -> Vec<f32> {
+    let mut spectrogram = SpectrogramBuilder::new()
+      .buffer_size(4096)
+      .decimation(Decimation::None)
+      .window(Window::Hann)
+      .hop(4096)
+      .build().unwrap();
+    spectrogram.set_high_pass(high_pass).unwrap();
+    
+    let samples = (0 .. 4096 * 13)
+      .map(|i| 0.5 + 0.5 * (2.0 * PI * 440.0 * i as f32 / 48000.0).sin())
+      .collect::<Vec<_>>();
+    // Past the transient of the filter, settled within a second.
+    spectrogram.process_planar(&samples[.. 4096 * 12], None, 48000.0).unwrap();
+    spectrogram.reset_accumulator();
+    spectrogram.process_planar(&samples[4096 * 12 ..], None, 48000.0).unwrap();
+    
+    let frame = spectrogram.frame().unwrap();
+    frame.iter().filter(|&(hz,_)| hz < 30.0).map(|(_,m)| m).collect()
+  }
+
+  #[test]
+  fn high_pass_removes_dc() {
+    let unfiltered = low_bins(None);
+    let filtered = low_bins(Some(20.0));
+    assert!(!unfiltered.is_empty());
+    for (&before,&after) in unfiltered.iter().zip(filtered.iter()) {
+      assert!(20.0 * (before / after).log10() >= 20.0, "{} {}", before, after);
+    }
+  }
+}
@@ -0,0 +1,131 @@
+use std::f64::consts::PI;
+use std::ops::Range;
+
+use rustfft::num_complex::Complex;
+use rustfft::num_traits::Zero;
+
+
+// Bins evaluated together, the recurrence being a chain of dependent
+// operations.
+const LANES: usize = 8;
+
+
+// Bins `bins` of the DFT of `output.len()` points of the real parts of
+// `samples`, zero-padded, evaluated with the Goertzel recurrence. The
+// other bins are zeroed. Same as the FFT up to rounding, phases included,
+// for `samples.len()` operations per bin instead of about
+// `log2(output.len())` per point.
+pub fn goertzel( samples: &[Complex<f32>],
+                 bins: Range<usize>,
+                 output: &mut [Complex<f32>] ) {
+  let n = output.len();
+  let len = samples.len();
+
+  for x in output.iter_mut() {
+    *x = Complex::zero();
+  }
+
+  // In f64, the recurrence loses precision for the low bins otherwise.
+  for first in bins.clone().step_by(LANES) {
+    let chunk = first .. (first + LANES).min(bins.end);
+    let mut coefficients = [0.0; LANES];
+    for (c,k) in coefficients.iter_mut().zip(chunk.clone()) {
+      *c = 2.0 * (2.0 * PI * k as f64 / n as f64).cos();
+    }
+
+    let mut s1 = [0.0; LANES];
+    let mut s2 = [0.0; LANES];
+    for x in samples {
+      let x = x.re as f64;
+      for i in 0 .. LANES {
+        let s = x + coefficients[i] * s1[i] - s2[i];
+        s2[i] = s1[i];
+        s1[i] = s;
+      }
+    }
+
+    // The recurrence ends `len` samples in rather than after the zeros,
+    // which only turn the phase.
+    for (i,k) in chunk.enumerate() {
+      let w = 2.0 * PI * k as f64 / n as f64;
+      let y = Complex::from_polar(&1.0, &w) * s1[i] - s2[i];
+      let bin = y * Complex::from_polar(&1.0, &(-w * len as f64));
+      output[k] = Complex::new(bin.re as f32, bin.im as f32);
+    }
+  }
+}
+
+// Cost of the Goertzel recurrence per sample and bin, relative to that of
+// the FFT per point and stage. A guess, not a measurement: both are about
+// a multiply-add.
+pub const GOERTZEL_COST: f32 = 1.0;
+
+// Whether evaluating `bins` bins with `goertzel` is cheaper than an FFT of
+// `fft_size` points on `samples` samples.
+pub fn goertzel_is_cheaper( bins: usize,
+                            samples: usize,
+                            fft_size: usize ) -> bool {
+  let goertzel = GOERTZEL_COST * (bins * samples) as f32;
+  goertzel < fft_size as f32 * (fft_size as f32).log2()
+}
+
+
+#[cfg(test)]
+mod tests {
+  use rustfft::FFTplanner;
+
+  use super::*;
+
+  // Windowless sum of sines of `(hz, amplitude)` at 48 kHz.
+  fn sines(tones: &[(f32,f32)], len: usize) -> Vec<Complex<f32>> {
+    (0 .. len).map(|i| {
+      let t = i as f32 / 48000.0;
+      let x = tones.iter()
+        .map(|&(hz,a)| a * (2.0 * std::f32::consts::PI * hz * t).sin())
+        .sum();
+      Complex::new(x, 0.0)
+    }).collect()
+  }
+
+  fn fft(samples: &[Complex<f32>], n: usize) -> Vec<Complex<f32>> {
+    let mut input = samples.to_vec();
+    input.resize(n, Complex::zero());
+    let mut output = vec![Complex::zero(); n];
+    FFTplanner::new(false).plan_fft(n).process(&mut input, &mut output);
+    output
+  }
+
+  #[test]
+  fn same_as_the_fft() {
+    let cases: [(&[(f32,f32)], usize); 4] = [
+      (&[(440.0, 0.5)], 4096),
+      (&[(61.0, 0.9)], 4096),
+      (&[(1000.0, 0.25), (3000.0, 0.25)], 4096),
+      // Zero-padded.
+      (&[(300.0, 0.5), (310.0, 0.1)], 3000),
+    ];
+    
+    for &(tones, len) in cases.iter() {
+      let samples = sines(tones, len);
+      let expected = fft(&samples, 4096);
+      let mut output = vec![Complex::new(1.0, 1.0); 4096];
+      goertzel(&samples, 3 .. 300, &mut output);
+      
+      let max = expected.iter().map(|x| x.norm()).fold(0.0, f32::max);
+      for (k,(x,y)) in output.iter().zip(expected.iter()).enumerate() {
+        if k >= 3 && k < 300 {
+          assert!((x - y).norm() < 1e-4 * max, "{:?} {} {} {}", tones, k, x, y);
+        } else {
+          assert_eq!(*x, Complex::zero());
+        }
+      }
+    }
+  }
+
+  #[test]
+  fn cheaper_for_few_bins() {
+    assert!(goertzel_is_cheaper(4, 4096, 4096));
+    assert!(!goertzel_is_cheaper(100, 4096, 4096));
+    assert!(!goertzel_is_cheaper(2048, 4096, 4096));
+  }
+}
@@ -0,0 +1,64 @@
+// Rows drawn from known frames, to catch changes to the mapping from
+// magnitudes to pixels.
+
+use crate::colormap::ColorMap;
+use crate::spectrogram::Spectrogram;
+
+
+const WIDTH: usize = 16;
+
+
+// A rising ramp with a peak at 1875 Hz, at 48 kHz.
+fn frame(spectrogram: &mut Spectrogram) {
+  let bins = spectrogram.last_spectrum().len();
+  let magnitudes = (0 .. bins)
+    .map(|bin| if bin == 10 { 0.05 } else { 0.05 * bin as f32 / bins as f32 })
+    .collect::<Vec<_>>();
+  spectrogram.set_frame_for_test(&magnitudes).unwrap();
+}
+
+fn draw(color_map: ColorMap) -> Vec<u8> {
+  let mut spectrogram = Spectrogram::new(8, 40.0, 87.0, 25.0);
+  spectrogram.set_color_map(color_map);
+  // Only sets the sample rate.
+  spectrogram.process_planar(&[], None, 48000.0).unwrap();
+  frame(&mut spectrogram);
+  
+  let mut row = vec![0; 4 * WIDTH];
+  spectrogram.draw_into_rgba(&mut row, WIDTH).unwrap();
+  row
+}
+
+fn check(row: &[u8], expected: &[[u8; 3]; WIDTH]) {
+  let colors = row.chunks(4).map(|p| [p[0], p[1], p[2]]).collect::<Vec<_>>();
+  assert_eq!(colors, &expected[..]);
+  assert!(row.chunks(4).all(|p| p[3] == u8::max_value()));
+}
+
+
+#[test]
+fn grayscale() {
+  check(&draw(ColorMap::Grayscale), &[
+    [  2,   2,   2], [  3,   3,   3], [  5,   5,   5], [  7,   7,   7],
+    [  9,   9,   9], [ 12,  12,  12], [ 15,  15,  15], [ 19,  19,  19],
+    [ 24,  24,  24], [ 28,  28,  28], [ 34,  34,  34], [111, 111, 111],
+    [ 49,  49,  49], [ 58,  58,  58], [ 67,  67,  67], [ 77,  77,  77],
+  ]);
+}
+
+#[test]
+fn viridis() {
+  check(&draw(ColorMap::Viridis), &[
+    [ 69,   4,  87], [ 69,   6,  89], [ 70,   8,  92], [ 70,  11,  94],
+    [ 71,  15,  98], [ 71,  19, 101], [ 72,  23, 105], [ 72,  28, 110],
+    [ 72,  35, 116], [ 72,  40, 120], [ 71,  47, 125], [ 39, 129, 142],
+    [ 66,  65, 134], [ 62,  75, 138], [ 57,  85, 140], [ 52,  96, 141],
+  ]);
+}
+
+#[test]
+fn wrong_frame_length() {
+  let mut spectrogram = Spectrogram::new(8, 40.0, 87.0, 25.0);
+  assert!(spectrogram.set_frame_for_test(&[0.0; 3]).is_err());
+  assert!(spectrogram.frame().is_none());
+}
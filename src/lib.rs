@@ -1,10 +1,28 @@
 #![feature(try_trait)]
 
-mod error;
-mod canvas;
-mod audio;
-mod ring;
-mod spectrogram;
+pub mod error;
+pub mod canvas;
+pub mod audio;
+pub mod ring;
+pub mod spectrogram;
+pub mod window;
+pub mod colormap;
+pub mod scale;
+pub mod filter;
+pub mod goertzel;
+pub mod onset;
+pub mod reassign;
+pub mod meter;
+pub mod noise;
+pub mod weighting;
+#[cfg(feature = "offline")]
+pub mod offline;
+#[cfg(feature = "testsignal")]
+pub mod testsignal;
+#[cfg(feature = "wasm")]
+pub mod bindings;
+#[cfg(test)]
+mod golden;
 
 
 use std::cell::RefCell;
@@ -29,7 +47,7 @@ pub fn main() {
   audio::start_processing( Processor {
     buffer_size: 512,
     process: Box::new(move |buffer| {
-      s.borrow_mut().process(buffer)
+      s.borrow_mut().process(buffer).map(|_| ())
     }),
   }).unwrap();
   
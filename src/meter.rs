@@ -0,0 +1,99 @@
+// Levels below this are reported as this.
+const FLOOR_DB: f32 = -100.0;
+
+// How fast the peak level falls back.
+const PEAK_DECAY_DB_PER_S: f32 = 20.0;
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Levels {
+  pub rms_db: f32,
+  pub peak_db: f32,
+  pub clipped_samples: u64,
+}
+
+// RMS as an exponential moving average over `window_ms`, and a decaying
+// instantaneous peak.
+pub struct LevelMeter {
+  window_ms: f32,
+  rms_decay: f32,
+  peak_decay: f32,
+  mean_square: f32,
+  peak: f32,
+  clipped_samples: u64,
+}
+
+
+impl Levels {
+  // The loudest of two levels.
+  pub fn max(self, other: Levels) -> Levels {
+    Levels {
+      rms_db: self.rms_db.max(other.rms_db),
+      peak_db: self.peak_db.max(other.peak_db),
+      clipped_samples: self.clipped_samples.max(other.clipped_samples),
+    }
+  }
+}
+
+impl LevelMeter {
+  pub fn new(window_ms: f32) -> LevelMeter {
+    let mut meter = LevelMeter {
+      window_ms: window_ms,
+      rms_decay: 0.0,
+      peak_decay: 0.0,
+      mean_square: 0.0,
+      peak: 0.0,
+      clipped_samples: 0,
+    };
+    meter.set_sample_rate(1.0);
+    meter
+  }
+
+  pub fn window_ms(&self) -> f32 {
+    self.window_ms
+  }
+
+  pub fn set_window_ms(&mut self, window_ms: f32, sample_rate: f32) {
+    self.window_ms = window_ms;
+    self.set_sample_rate(sample_rate);
+  }
+
+  pub fn set_sample_rate(&mut self, sample_rate: f32) {
+    let sample_ms = 1000.0 / sample_rate;
+    self.rms_decay = (-sample_ms / self.window_ms).exp();
+    self.peak_decay = 10.0_f32.powf(-PEAK_DECAY_DB_PER_S / 20.0 / sample_rate);
+  }
+
+  pub fn push(&mut self, value: f32) {
+    let a = self.rms_decay;
+    self.mean_square = a * self.mean_square + (1.0 - a) * value * value;
+    self.peak = (self.peak * self.peak_decay).max(value.abs());
+
+    if value.abs() >= 1.0 {
+      self.clipped_samples += 1;
+    }
+  }
+
+  pub fn levels(&self) -> Levels {
+    Levels {
+      rms_db: to_db(self.mean_square.sqrt()),
+      peak_db: to_db(self.peak),
+      clipped_samples: self.clipped_samples,
+    }
+  }
+
+  pub fn reset(&mut self) {
+    self.mean_square = 0.0;
+    self.peak = 0.0;
+    self.clipped_samples = 0;
+  }
+}
+
+
+fn to_db(value: f32) -> f32 {
+  if value > 0.0 {
+    (20.0 * value.log10()).max(FLOOR_DB)
+  } else {
+    FLOOR_DB
+  }
+}
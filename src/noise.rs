@@ -0,0 +1,197 @@
+// Sub-windows the minimum of each bin is tracked over.
+const SUBWINDOWS: usize = 8;
+
+// Time constant the magnitudes are smoothed with before taking their
+// minimum, which would otherwise be far below the average noise.
+const SMOOTHING_S: f32 = 0.5;
+
+// Ratio of the average noise to the minimum of the smoothed magnitudes.
+const BIAS: f32 = 1.5;
+
+
+// Noise floor of a spectrum by minimum statistics: the minimum of each
+// smoothed bin over the last `window_s` seconds, corrected by `BIAS`. It
+// falls with the noise at once, and rises within `window_s`, as the
+// minima of the oldest of the `SUBWINDOWS` sub-windows are dropped. Notes
+// held for less than that are left out.
+pub struct NoiseFloor {
+  window_s: f32,
+  smoothed: Vec<f32>,
+  minima: Vec<f32>,
+  current: Vec<f32>,
+  floor: Vec<f32>,
+  primed: bool,
+  slot: usize,
+  elapsed_s: f32,
+}
+
+
+impl NoiseFloor {
+  pub fn new(bins: usize, window_s: f32) -> NoiseFloor {
+    NoiseFloor {
+      window_s: window_s,
+      smoothed: vec![0.0; bins],
+      minima: vec![std::f32::INFINITY; SUBWINDOWS * bins],
+      current: vec![std::f32::INFINITY; bins],
+      floor: vec![0.0; bins],
+      primed: false,
+      slot: 0,
+      elapsed_s: 0.0,
+    }
+  }
+
+  // Takes in the `bins` magnitudes of a frame `elapsed_s` seconds after
+  // the previous one, starting over if the number of bins changed.
+  pub fn update( &mut self,
+                 bins: usize,
+                 magnitude: impl Fn(usize) -> f32,
+                 elapsed_s: f32 ) {
+    if bins != self.current.len() {
+      *self = NoiseFloor::new(bins, self.window_s);
+    }
+
+    // The first frame is taken as is.
+    let a = if self.primed { (-elapsed_s / SMOOTHING_S).exp() } else { 0.0 };
+    self.primed = true;
+    
+    let bins_iter = self.smoothed.iter_mut().zip(self.current.iter_mut());
+    for (bin,(smoothed,current)) in bins_iter.enumerate() {
+      *smoothed = a * *smoothed + (1.0 - a) * magnitude(bin);
+      *current = current.min(*smoothed);
+    }
+
+    self.elapsed_s += elapsed_s;
+    if self.elapsed_s >= self.window_s / SUBWINDOWS as f32 {
+      self.elapsed_s = 0.0;
+      let slot = &mut self.minima[self.slot * bins .. (self.slot + 1) * bins];
+      slot.copy_from_slice(&self.current);
+      self.slot = (self.slot + 1) % SUBWINDOWS;
+      for current in self.current.iter_mut() {
+        *current = std::f32::INFINITY;
+      }
+    }
+
+    for (bin,floor) in self.floor.iter_mut().enumerate() {
+      *floor = BIAS * self.minima.iter().skip(bin).step_by(bins)
+        .fold(self.current[bin], |a,&b| a.min(b));
+    }
+  }
+
+  // Zero until the first frame.
+  pub fn floor(&self) -> &[f32] {
+    &self.floor
+  }
+
+  pub fn reset(&mut self) {
+    for x in self.minima.iter_mut().chain(self.current.iter_mut()) {
+      *x = std::f32::INFINITY;
+    }
+    for x in self.smoothed.iter_mut().chain(self.floor.iter_mut()) {
+      *x = 0.0;
+    }
+    self.primed = false;
+    self.slot = 0;
+    self.elapsed_s = 0.0;
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use std::f32::consts::PI;
+
+  use crate::spectrogram::FrameData;
+  use crate::spectrogram::SpectrogramBuilder;
+  use crate::window::Window;
+
+  use super::*;
+
+  // Floor of a single bin held at `magnitude` for `seconds`, in frames of
+  // 10 ms.
+  fn hold(floor: &mut NoiseFloor, magnitude: f32, seconds: f32) -> f32 {
+    for _ in 0 .. (seconds * 100.0).round() as usize {
+      floor.update(1, |_| magnitude, 0.01);
+    }
+    floor.floor()[0]
+  }
+
+  #[test]
+  fn minimum_statistics() {
+    let mut floor = NoiseFloor::new(1, 1.0);
+    assert_eq!(floor.floor(), &[0.0]);
+    assert_eq!(hold(&mut floor, 2.0, 1.0), BIAS * 2.0);
+    
+    // A note shorter than the window is left out, a longer one isn't.
+    assert_eq!(hold(&mut floor, 10.0, 0.5), BIAS * 2.0);
+    assert!(hold(&mut floor, 10.0, 2.0) > BIAS * 9.0);
+    
+    // Falling back at once, but for the smoothing.
+    assert!(hold(&mut floor, 2.0, 5.0 * SMOOTHING_S) < BIAS * 2.1);
+    
+    floor.reset();
+    assert_eq!(floor.floor(), &[0.0]);
+    floor.update(2, |bin| bin as f32, 0.01);
+    assert_eq!(floor.floor(), &[0.0, BIAS]);
+  }
+
+  #[test]
+  fn tone_over_pink_noise() {
+    let new = |reduction| {
+      let mut spectrogram = SpectrogramBuilder::new()
+        .buffer_size(2048)
+        .window(Window::Hann)
+        .hop(512)
+        .key_range(30.0, 70.0)
+        .build().unwrap();
+      if reduction {
+        spectrogram.set_noise_reduction(true, 1.0).unwrap();
+      }
+      spectrogram
+    };
+    let (mut plain, mut reduced) = (new(false), new(true));
+    
+    // Low-passed white noise for 14 s, and a tone over the last 2 s,
+    // within the window of the floor.
+    let mut state = 7_u32;
+    let mut low_passed = 0.0;
+    let mut row = vec![0; 4 * 100];
+    let mut frames = None;
+    for chunk in 0 .. 14 * 48000 / 1024 {
+      let samples = (0 .. 1024).map(|i| {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        let white = state as f32 / std::u32::MAX as f32 * 2.0 - 1.0;
+        low_passed = 0.95 * low_passed + 0.05 * white;
+        
+        let t = (chunk * 1024 + i) as f32 / 48000.0;
+        let tone = (2.0 * PI * 440.0 * t).sin();
+        0.3 * low_passed + if t > 12.0 { 0.1 * tone } else { 0.0 }
+      }).collect::<Vec<_>>();
+      
+      plain.process_planar(&samples, None, 48000.0).unwrap();
+      reduced.process_planar(&samples, None, 48000.0).unwrap();
+      frames = Some((plain.frame_data(), reduced.frame_data()));
+      plain.draw_row_rgba(&mut row).unwrap();
+      reduced.draw_row_rgba(&mut row).unwrap();
+    }
+    
+    let (plain, reduced) = frames.unwrap();
+    assert!(plain.noise_floor.is_none());
+    let floor = reduced.noise_floor.as_ref().unwrap();
+    assert_eq!(floor.len(), reduced.bins.len());
+    
+    let tone = |frame: &FrameData| {
+      frame.bins.iter()
+        .filter(|bin| (bin.hz - 440.0).abs() < 30.0)
+        .map(|bin| bin.magnitude)
+        .fold(0.0, f32::max)
+    };
+    let band = |frame: &FrameData| {
+      let bins = frame.bins.iter().filter(|bin| bin.hz < 300.0);
+      bins.clone().map(|bin| bin.magnitude).sum::<f32>() / bins.count() as f32
+    };
+    assert!(tone(&reduced) > 0.9 * tone(&plain));
+    assert!(band(&reduced) < 0.3 * band(&plain));
+  }
+}
@@ -0,0 +1,199 @@
+use crate::canvas::Line;
+use crate::canvas::LineData;
+use crate::canvas::PixelFormat;
+use crate::error::Error;
+use crate::spectrogram::SpectrogramBuilder;
+use crate::spectrogram::SpectrogramConfig;
+
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+// Decoded PCM, interleaved.
+pub struct Wav {
+  pub sample_rate: f32,
+  pub channels: usize,
+  pub samples: Vec<f32>,
+}
+
+struct Format {
+  code: u16,
+  channels: usize,
+  sample_rate: u32,
+  bits: u16,
+}
+
+
+impl Wav {
+  // Parses 16-bit integer and 32-bit float PCM.
+  pub fn parse(bytes: &[u8]) -> Result<Wav,Error> {
+    if bytes.len() < 12
+      || &bytes[0 .. 4] != b"RIFF"
+      || &bytes[8 .. 12] != b"WAVE" {
+      return Err(Error::Wav("not a RIFF WAVE file"));
+    }
+
+    let mut format = None;
+    let mut chunks = &bytes[12 ..];
+
+    while chunks.len() >= 8 {
+      let id = &chunks[0 .. 4];
+      let len = u32_at(chunks, 4) as usize;
+      // The length is untrusted, and `usize` is 32 bits on wasm.
+      let body = 8_usize.checked_add(len)
+        .and_then(|end| chunks.get(8 .. end))
+        .ok_or(Error::Wav("truncated chunk"))?;
+
+      match id {
+        b"fmt " => format = Some(Format::parse(body)?),
+        b"data" => {
+          let format = format.ok_or(Error::Wav("data before fmt chunk"))?;
+          return format.decode(body);
+        },
+        _ => (),
+      }
+
+      // Chunks are padded to an even length. `8 + len` is within
+      // `chunks`, the body being there.
+      let next = (8 + len + len % 2).min(chunks.len());
+      chunks = &chunks[next ..];
+    }
+
+    Err(Error::Wav("missing data chunk"))
+  }
+
+  pub fn frames(&self) -> usize {
+    self.samples.len() / self.channels
+  }
+}
+
+impl Format {
+  fn parse(body: &[u8]) -> Result<Format,Error> {
+    if body.len() < 16 {
+      return Err(Error::Wav("truncated fmt chunk"));
+    }
+
+    let mut code = u16_at(body, 0);
+    if code == FORMAT_EXTENSIBLE {
+      if body.len() < 26 {
+        return Err(Error::Wav("truncated fmt chunk"));
+      }
+      code = u16_at(body, 24);
+    }
+
+    let format = Format {
+      code: code,
+      channels: u16_at(body, 2) as usize,
+      sample_rate: u32_at(body, 4),
+      bits: u16_at(body, 14),
+    };
+
+    if format.channels == 0 {
+      return Err(Error::Wav("no channels"));
+    }
+    if format.sample_rate == 0 {
+      return Err(Error::Wav("sample rate of 0"));
+    }
+
+    match (format.code, format.bits) {
+      (FORMAT_PCM, 16) | (FORMAT_FLOAT, 32) => Ok(format),
+      (FORMAT_PCM, _) | (FORMAT_FLOAT, _) =>
+        Err(Error::Wav("unsupported sample size")),
+      _ => Err(Error::Wav("unsupported codec")),
+    }
+  }
+
+  fn decode(self, data: &[u8]) -> Result<Wav,Error> {
+    let samples = match self.code {
+      FORMAT_PCM => data.chunks_exact(2)
+        .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0)
+        .collect(),
+      _ => data.chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect(),
+    };
+
+    let mut wav = Wav {
+      sample_rate: self.sample_rate as f32,
+      channels: self.channels,
+      samples: samples,
+    };
+
+    // Drops a trailing partial frame.
+    let len = wav.frames() * wav.channels;
+    wav.samples.truncate(len);
+
+    Ok(wav)
+  }
+}
+
+
+// Draws the whole file as `width` columns of `height` pixels, laid out
+// like `ImageData.data` with the low frequencies at the bottom.
+pub fn analyze_wav( bytes: &[u8],
+                    config: &SpectrogramConfig,
+                    width: usize,
+                    height: usize ) -> Result<Vec<u8>,Error> {
+  let wav = Wav::parse(bytes)?;
+  let mut spectrogram = SpectrogramBuilder::from_config(*config).build()?;
+
+  let hop = (wav.frames() / width.max(1)).max(1);
+  spectrogram.set_hop(hop);
+
+  let mut image = vec![0; 4 * width * height];
+  let mut column = vec![0; 4 * height];
+
+  let columns = wav.samples.chunks(hop * wav.channels).take(width);
+  for (x,samples) in columns.enumerate() {
+    spectrogram.process_interleaved(samples, wav.channels, wav.sample_rate)?;
+
+    if spectrogram.frame().is_none() {
+      continue;
+    }
+
+    spectrogram.draw_column( Line {
+      len: height,
+      data: &mut LineData::new(&mut column, PixelFormat::Rgba8),
+    }, true)?;
+
+    for (y,pixel) in column.chunks_exact(4).enumerate() {
+      let i = 4 * (y * width + x);
+      image[i .. i + 4].copy_from_slice(pixel);
+    }
+  }
+
+  Ok(image)
+}
+
+fn u16_at(bytes: &[u8], i: usize) -> u16 {
+  u16::from_le_bytes([bytes[i], bytes[i + 1]])
+}
+
+fn u32_at(bytes: &[u8], i: usize) -> u32 {
+  u32::from_le_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]])
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn chunk(id: &[u8], body: &[u8]) -> Vec<u8> {
+    let mut chunk = id.to_vec();
+    chunk.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    chunk.extend_from_slice(body);
+    chunk
+  }
+
+  #[test]
+  fn oversized_chunk() {
+    let mut bytes = b"RIFF\0\0\0\0WAVE".to_vec();
+    bytes.extend(chunk(b"LIST", &[0; 6]));
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&u32::max_value().to_le_bytes());
+    bytes.extend_from_slice(&[0; 16]);
+    
+    assert_eq!(Wav::parse(&bytes).err(), Some(Error::Wav("truncated chunk")));
+  }
+}
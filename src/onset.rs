@@ -0,0 +1,88 @@
+// Untaken onsets beyond this are dropped.
+const MAX_EVENTS: usize = 64;
+
+
+// An onset, `offset` counting input samples since the start.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OnsetEvent {
+  pub offset: u64,
+  pub flux: f32,
+}
+
+// Positive spectral flux between consecutive frames, reported as an onset
+// when it exceeds `threshold` times its moving average while the energy of
+// the frame rises.
+pub struct OnsetDetector {
+  threshold: f32,
+  previous: Vec<f32>,
+  flux: f32,
+  change: f32,
+  mean: f32,
+  last: Option<u64>,
+  events: Vec<OnsetEvent>,
+}
+
+
+impl OnsetDetector {
+  pub fn new(bins: usize, threshold: f32) -> OnsetDetector {
+    OnsetDetector {
+      threshold: threshold,
+      previous: vec![0.0; bins],
+      flux: 0.0,
+      change: 0.0,
+      mean: 0.0,
+      last: None,
+      events: Vec::with_capacity(MAX_EVENTS),
+    }
+  }
+
+  pub fn threshold(&self) -> f32 {
+    self.threshold
+  }
+
+  pub fn set_threshold(&mut self, threshold: f32) {
+    self.threshold = threshold;
+  }
+
+  pub fn push_bin(&mut self, bin: usize, magnitude: f32) {
+    let previous = &mut self.previous[bin];
+    self.flux += (magnitude - *previous).max(0.0);
+    self.change += magnitude * magnitude - *previous * *previous;
+    *previous = magnitude;
+  }
+
+  // Ends the frame computed at `offset`, `decay` being the weight of the
+  // moving average. Frames above the threshold less than `min_gap`
+  // samples after the previous one belong to the same onset.
+  pub fn end_frame(&mut self, offset: u64, min_gap: u64, decay: f32) {
+    let flux = self.flux;
+    let change = self.change;
+    self.flux = 0.0;
+    self.change = 0.0;
+
+    if flux > self.threshold * self.mean && change > 0.0 {
+      let gap = self.last.map_or(true, |last| offset - last >= min_gap);
+      if gap && self.events.len() < MAX_EVENTS {
+        self.events.push(OnsetEvent { offset: offset, flux: flux });
+      }
+      self.last = Some(offset);
+    }
+
+    self.mean = decay * self.mean + (1.0 - decay) * flux;
+  }
+
+  pub fn take(&mut self) -> Vec<OnsetEvent> {
+    self.events.drain(..).collect()
+  }
+
+  pub fn reset(&mut self) {
+    for x in self.previous.iter_mut() {
+      *x = 0.0;
+    }
+    self.flux = 0.0;
+    self.change = 0.0;
+    self.mean = 0.0;
+    self.last = None;
+    self.events.clear();
+  }
+}
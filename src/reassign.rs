@@ -0,0 +1,198 @@
+use std::f32::consts::PI;
+
+use rustfft::num_complex::Complex;
+
+
+// Bins of the refined spectrum per bin of the FFT.
+pub const REFINE_FACTOR: usize = 4;
+
+
+// Moves the magnitude of each bin to its instantaneous frequency, derived
+// from the phase advance since the previous frame, on a grid
+// `REFINE_FACTOR` times finer than the bins. The phase advance is only
+// unambiguous for hops of at most a quarter of the window.
+pub struct Reassigner {
+  phases: Vec<f32>,
+  refined: Vec<f32>,
+  primed: bool,
+}
+
+
+impl Reassigner {
+  pub fn new(fft_size: usize) -> Reassigner {
+    Reassigner {
+      phases: vec![0.0; fft_size / 2],
+      refined: vec![0.0; refined_len(fft_size)],
+      primed: false,
+    }
+  }
+
+  // Refined magnitudes of the non-DC bins of `output`, `hop` samples
+  // after the previous frame. Magnitudes landing on the same refined bin
+  // add up in power, and stay at the bin centers for the first frame.
+  pub fn reassign( &mut self,
+                   output: &[Complex<f32>],
+                   hop: usize,
+                   scale: f32 ) -> &[f32] {
+    let n = output.len();
+
+    for x in self.refined.iter_mut() {
+      *x = 0.0;
+    }
+
+    for (k,bin) in output.iter().enumerate().take(n / 2).skip(1) {
+      let magnitude = scale * bin.norm();
+      let phase = bin.arg();
+
+      let position = if self.primed && hop > 0 {
+        let expected = 2.0 * PI * (k * hop % n) as f32 / n as f32;
+        let deviation = wrap(phase - self.phases[k] - expected);
+        k as f32 + deviation * n as f32 / (2.0 * PI * hop as f32)
+      } else {
+        k as f32
+      };
+      self.phases[k] = phase;
+
+      let i = (position * REFINE_FACTOR as f32).round() as isize - 1;
+      if i >= 0 && (i as usize) < self.refined.len() {
+        self.refined[i as usize] += magnitude * magnitude;
+      }
+    }
+
+    for x in self.refined.iter_mut() {
+      *x = x.sqrt();
+    }
+
+    self.primed = true;
+    &self.refined
+  }
+
+  // The next frame isn't contiguous with the previous one.
+  pub fn reset(&mut self) {
+    self.primed = false;
+  }
+}
+
+
+// Non-DC bins of the refined spectrum of an FFT of `fft_size` points.
+pub fn refined_len(fft_size: usize) -> usize {
+  REFINE_FACTOR * fft_size / 2 - 1
+}
+
+// Same angle between -PI and PI.
+fn wrap(angle: f32) -> f32 {
+  angle - 2.0 * PI * ((angle + PI) / (2.0 * PI)).floor()
+}
+
+
+#[cfg(test)]
+mod tests {
+  use rustfft::FFTplanner;
+
+  use crate::canvas::Line;
+  use crate::canvas::LineData;
+  use crate::canvas::PixelFormat;
+  use crate::colormap::ColorMap;
+  use crate::spectrogram::SpectrogramBuilder;
+  use crate::window::Window;
+
+  use super::*;
+
+  const HZ_PER_BIN: f32 = 48000.0 / 4096.0;
+
+  // FFT of 4096 points of a Hann-windowed sine at `hz`, from sample
+  // `offset` at 48 kHz.
+  fn spectrum(hz: f32, offset: usize) -> Vec<Complex<f32>> {
+    let window = Window::Hann.coefficients(4096);
+    let mut input = (0 .. 4096).map(|i| {
+      let t = (offset + i) as f32 / 48000.0;
+      Complex::new(window[i] * (2.0 * PI * hz * t).sin(), 0.0)
+    }).collect::<Vec<_>>();
+    let mut output = vec![Complex::new(0.0, 0.0); 4096];
+    FFTplanner::new(false).plan_fft(4096).process(&mut input, &mut output);
+    output
+  }
+
+  // Share of the power of `refined` in its bin nearest `hz`.
+  fn share_at(refined: &[f32], hz: f32) -> f32 {
+    let i = (hz / HZ_PER_BIN * REFINE_FACTOR as f32).round() as usize - 1;
+    let total = refined.iter().map(|m| m * m).sum::<f32>();
+    refined[i] * refined[i] / total
+  }
+
+  #[test]
+  fn sine_between_bins() {
+    // 61 Hz lies between bins 5 and 6.
+    let mut reassigner = Reassigner::new(4096);
+    let first = reassigner.reassign(&spectrum(61.0, 0), 1024, 1.0).to_vec();
+    let second = reassigner.reassign(&spectrum(61.0, 1024), 1024, 1.0);
+    assert_eq!(second.len(), refined_len(4096));
+    // Split at first, the bins being left at their centers.
+    let best = first.iter().map(|m| m * m).fold(0.0, f32::max);
+    assert!(best / first.iter().map(|m| m * m).sum::<f32>() < 0.7);
+    assert!(share_at(second, 61.0) > 0.9);
+  }
+
+  // Shades of a row of 88 keys of a 61 Hz sine, one key per pixel.
+  fn pixels(refine: bool) -> Vec<u8> {
+    let mut spectrogram = SpectrogramBuilder::new()
+      .buffer_size(4096)
+      .window(Window::Hann)
+      .hop(1024)
+      .key_range(1.0, 88.0)
+      .color_map(ColorMap::Grayscale)
+      .build().unwrap();
+    spectrogram.set_refine(refine);
+    let samples = (0 .. 48000)
+      .map(|i| 0.5 * (2.0 * PI * 61.0 * i as f32 / 48000.0).sin())
+      .collect::<Vec<_>>();
+    spectrogram.process_planar(&samples, None, 48000.0).unwrap();
+    
+    let mut rgba = vec![0; 4 * 88];
+    spectrogram.draw_frame_peek( Line {
+      len: 88,
+      data: &mut LineData::new(&mut rgba, PixelFormat::Rgba8),
+    }).unwrap();
+    rgba.chunks(4).map(|p| p[0]).collect()
+  }
+
+  #[test]
+  fn energy_on_one_pixel() {
+    // Key 14.8: 61 Hz is on the 15th pixel.
+    let spread = pixels(false);
+    let total = spread.iter().map(|&x| u32::from(x)).sum::<u32>();
+    assert!(2 * u32::from(spread[14]) < total, "{:?}", spread);
+    
+    let refined = pixels(true);
+    assert!(refined[14] > 0);
+    for (x,&shade) in refined.iter().enumerate().filter(|&(x,_)| x != 14) {
+      assert!(10 * u32::from(shade) < u32::from(refined[14]), "{}", x);
+    }
+  }
+
+  #[test]
+  fn reset_starts_over() {
+    let mut reassigner = Reassigner::new(4096);
+    let first = reassigner.reassign(&spectrum(61.0, 0), 1024, 1.0).to_vec();
+    reassigner.reset();
+    let again = reassigner.reassign(&spectrum(61.0, 1024), 1024, 1.0);
+    // At the bin centers, the hop being forgotten.
+    let centers = |refined: &[f32]| {
+      refined.iter().enumerate()
+        .all(|(i,&m)| m == 0.0 || (i + 1) % REFINE_FACTOR == 0)
+    };
+    assert!(centers(&first));
+    assert!(centers(again));
+  }
+
+  #[test]
+  fn wrapped_angles() {
+    let angles = [
+      (0.0, 0.0), (1.5 * PI, -0.5 * PI), (-1.5 * PI, 0.5 * PI),
+      (4.5 * PI, 0.5 * PI),
+    ];
+    for &(angle, wrapped) in angles.iter() {
+      assert!((wrap(angle) - wrapped).abs() < 1e-5, "{}", angle);
+    }
+  }
+}
@@ -1,10 +1,42 @@
 use std::ops::Deref;
 use std::ops::DerefMut;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 
 
+// The second half of `data` mirrors the first one, so that the items
+// are always contiguous from the oldest at `pos` to the newest. The ring
+// always dereferences to `capacity` items, the initial value filling in
+// for the items not enqueued yet.
 pub struct Ring<T> {
   data: Vec<T>,
   pos: usize,
+  len: usize,
+}
+
+
+// What `SampleQueue::push_slice` does with the samples that don't fit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Overflow {
+  DropOldest,
+  Reject,
+}
+
+// Fixed-capacity queue of samples between one producer and one consumer
+// on other threads, such as the audio thread and a worker sharing the
+// wasm memory, without locks. Everything is atomic, samples included as
+// their bits, so that a consumer racing a producer dropping the oldest
+// samples only has to retry. More than one producer or consumer at a
+// time may lose or repeat samples.
+pub struct SampleQueue {
+  data: Box<[AtomicU32]>,
+  // Positions of the next sample written and read, wrapping.
+  head: AtomicUsize,
+  tail: AtomicUsize,
+  overflow: Overflow,
+  dropped: AtomicUsize,
 }
 
 
@@ -13,21 +45,99 @@ impl<T: Copy> Ring<T> {
     Ring {
       data: vec![value; size * 2],
       pos: 0,
+      len: 0,
     }
   }
-  
+
+  // Number of items enqueued, up to `capacity`.
+  pub fn len(&self) -> usize {
+    self.len
+  }
+
+  pub fn capacity(&self) -> usize {
+    self.data.len() / 2
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  pub fn is_full(&self) -> bool {
+    self.len == self.capacity()
+  }
+
+  // Overwrites the oldest item once full.
   pub fn enqueue(&mut self, value: T) {
     let size = self.data.len() / 2;
-    
-    if self.pos > 0 {
-      self.data[self.pos - 1] = value;
+    if size == 0 {
+      return;
     }
-    
-    if self.pos < size {
-      self.data[self.pos + size] = value;
+
+    self.data[self.pos] = value;
+    self.data[self.pos + size] = value;
+
+    self.pos = (self.pos + 1) % size;
+    self.len = (self.len + 1).min(size);
+  }
+
+  // Same as enqueuing each item in turn.
+  pub fn extend_from_slice(&mut self, items: &[T]) {
+    let size = self.data.len() / 2;
+    if size == 0 {
+      return;
     }
-    
-    self.pos = (self.pos + 1) % (size + 1);
+
+    let items = &items[items.len().saturating_sub(size) ..];
+    let start = self.pos;
+    let end = start + items.len();
+
+    self.data[start .. end].copy_from_slice(items);
+
+    if end <= size {
+      self.data[start + size .. end + size].copy_from_slice(items);
+    } else {
+      let split = size - start;
+      self.data[start + size ..].copy_from_slice(&items[.. split]);
+      self.data[.. end - size].copy_from_slice(&items[split ..]);
+    }
+
+    self.pos = end % size;
+    self.len = (self.len + items.len()).min(size);
+  }
+
+  // Keeps the newest items that fit, growing with older `fill` items.
+  pub fn resize(&mut self, size: usize, fill: T) {
+    let keep = size.min(self.len);
+    let mut ring = Ring::new(size, fill);
+    ring.extend_from_slice(&self[self.capacity() - keep ..]);
+    *self = ring;
+  }
+
+  // Forgets the items enqueued, `value` filling in for all of them.
+  pub fn fill(&mut self, value: T) {
+    for x in self.data.iter_mut() {
+      *x = value;
+    }
+    self.pos = 0;
+    self.len = 0;
+  }
+
+  // Items from the oldest to the newest. The storage being mirrored, the
+  // second slice is always empty.
+  pub fn as_slices(&self) -> (&[T], &[T]) {
+    (self, &[])
+  }
+
+  // Copies the oldest items first, as many as fit in `out`.
+  pub fn copy_to_slice(&self, out: &mut [T]) {
+    let len = out.len().min(self.capacity());
+    out[.. len].copy_from_slice(&self[.. len]);
+  }
+}
+
+impl<T: Copy + Default> Ring<T> {
+  pub fn clear(&mut self) {
+    self.fill(T::default());
   }
 }
 
@@ -45,3 +155,263 @@ impl<T> DerefMut for Ring<T> {
     &mut self.data[self.pos .. self.pos + size]
   }
 }
+
+impl SampleQueue {
+  pub fn new(capacity: usize, overflow: Overflow) -> SampleQueue {
+    SampleQueue {
+      data: (0 .. capacity).map(|_| AtomicU32::new(0)).collect(),
+      head: AtomicUsize::new(0),
+      tail: AtomicUsize::new(0),
+      overflow: overflow,
+      dropped: AtomicUsize::new(0),
+    }
+  }
+
+  pub fn capacity(&self) -> usize {
+    self.data.len()
+  }
+
+  pub fn overflow(&self) -> Overflow {
+    self.overflow
+  }
+
+  // Samples waiting to be popped.
+  pub fn len(&self) -> usize {
+    let tail = self.tail.load(Ordering::Acquire);
+    let head = self.head.load(Ordering::Acquire);
+    head.wrapping_sub(tail).min(self.capacity())
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len() == 0
+  }
+
+  // Samples dropped or rejected on overflow so far.
+  pub fn dropped(&self) -> usize {
+    self.dropped.load(Ordering::Relaxed)
+  }
+
+  // Producer side. Returns the number of samples queued, the newest ones
+  // that fit when dropping the oldest.
+  pub fn push_slice(&self, samples: &[f32]) -> usize {
+    let capacity = self.capacity();
+    let head = self.head.load(Ordering::Relaxed);
+    let tail = self.tail.load(Ordering::Acquire);
+    let free = capacity - head.wrapping_sub(tail).min(capacity);
+    
+    let total = samples.len();
+    let samples = match self.overflow {
+      Overflow::Reject => &samples[.. samples.len().min(free)],
+      Overflow::DropOldest => &samples[samples.len().saturating_sub(capacity) ..],
+    };
+    let mut dropped = total - samples.len();
+    
+    if samples.len() > free {
+      // Moves the oldest samples out of the way of the consumer before
+      // overwriting them.
+      let new_tail = head.wrapping_add(samples.len()).wrapping_sub(capacity);
+      let mut tail = tail;
+      while (new_tail.wrapping_sub(tail) as isize) > 0 {
+        match self.tail.compare_exchange_weak( tail, new_tail,
+                                               Ordering::AcqRel,
+                                               Ordering::Acquire ) {
+          Ok(_) => {
+            dropped += new_tail.wrapping_sub(tail);
+            break;
+          },
+          Err(current) => tail = current,
+        }
+      }
+    }
+    
+    for (i,x) in samples.iter().enumerate() {
+      let slot = head.wrapping_add(i) % capacity;
+      self.data[slot].store(x.to_bits(), Ordering::Relaxed);
+    }
+    self.head.store(head.wrapping_add(samples.len()), Ordering::Release);
+    
+    self.dropped.fetch_add(dropped, Ordering::Relaxed);
+    samples.len()
+  }
+
+  // Consumer side. Moves the oldest samples into `out`, as many as it
+  // holds, returning their number.
+  pub fn pop_into(&self, out: &mut [f32]) -> usize {
+    let capacity = self.capacity();
+    
+    loop {
+      let tail = self.tail.load(Ordering::Acquire);
+      let head = self.head.load(Ordering::Acquire);
+      let len = head.wrapping_sub(tail).min(capacity).min(out.len());
+      
+      for (i,x) in out[.. len].iter_mut().enumerate() {
+        let slot = tail.wrapping_add(i) % capacity;
+        *x = f32::from_bits(self.data[slot].load(Ordering::Relaxed));
+      }
+      
+      // The producer moving the tail meanwhile may have overwritten them.
+      let new_tail = tail.wrapping_add(len);
+      if self.tail.compare_exchange( tail, new_tail,
+                                     Ordering::AcqRel,
+                                     Ordering::Acquire ).is_ok() {
+        return len;
+      }
+    }
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use std::collections::VecDeque;
+
+  use super::*;
+
+  #[test]
+  fn extend_from_slice() {
+    // Exact fit.
+    let mut ring = Ring::new(4, 0);
+    ring.extend_from_slice(&[1, 2, 3, 4]);
+    assert_eq!(&ring[..], &[1, 2, 3, 4]);
+    assert!(ring.is_full());
+    
+    // Wrapping around the end of the storage.
+    let mut ring = Ring::new(4, 0);
+    ring.extend_from_slice(&[1, 2, 3]);
+    ring.extend_from_slice(&[4, 5, 6]);
+    assert_eq!(&ring[..], &[3, 4, 5, 6]);
+    ring.enqueue(7);
+    assert_eq!(&ring[..], &[4, 5, 6, 7]);
+    
+    // Longer than the ring, keeping the newest items.
+    let mut ring = Ring::new(4, 0);
+    ring.enqueue(1);
+    ring.extend_from_slice(&[2, 3, 4, 5, 6, 7]);
+    assert_eq!(&ring[..], &[4, 5, 6, 7]);
+    assert_eq!(ring.len(), 4);
+    
+    let mut empty = Ring::new(0, 0);
+    empty.extend_from_slice(&[1, 2]);
+    assert!(empty.is_empty());
+  }
+
+  fn linear(ring: &Ring<i32>) -> Vec<i32> {
+    let (a, b) = ring.as_slices();
+    let mut out = vec![-1; ring.capacity()];
+    ring.copy_to_slice(&mut out);
+    assert_eq!([a, b].concat(), out);
+    out
+  }
+
+  #[test]
+  fn slices() {
+    let mut ring = Ring::new(4, 0);
+    ring.extend_from_slice(&[1, 2, 3]);
+    assert_eq!(linear(&ring), &[0, 1, 2, 3]);
+    
+    // Wrapped, from the oldest to the newest.
+    ring.extend_from_slice(&[4, 5, 6]);
+    assert_eq!(linear(&ring), &[3, 4, 5, 6]);
+    
+    // A shorter copy takes the oldest items.
+    let mut out = [0; 2];
+    ring.copy_to_slice(&mut out);
+    assert_eq!(out, [3, 4]);
+    
+    let empty = Ring::new(0, 0);
+    assert_eq!(empty.as_slices(), (&[][..], &[][..]));
+    assert!(linear(&empty).is_empty());
+  }
+
+  #[test]
+  fn resize() {
+    let mut ring = Ring::new(4, 0);
+    ring.extend_from_slice(&[1, 2, 3, 4, 5]);
+    ring.resize(2, 9);
+    assert_eq!(&ring[..], &[4, 5]);
+    
+    // Growing pads with older fill items.
+    ring.resize(5, 9);
+    assert_eq!(&ring[..], &[9, 9, 9, 4, 5]);
+    assert_eq!(ring.len(), 2);
+    ring.enqueue(6);
+    assert_eq!(&ring[..], &[9, 9, 4, 5, 6]);
+    
+    ring.resize(0, 9);
+    assert_eq!(ring.capacity(), 0);
+    assert!(ring.is_empty());
+    ring.enqueue(1);
+    assert!(ring.is_empty());
+    
+    // Growing from empty.
+    ring.resize(3, 7);
+    assert_eq!(&ring[..], &[7, 7, 7]);
+    assert!(ring.is_empty());
+    ring.enqueue(1);
+    assert_eq!(&ring[..], &[7, 7, 1]);
+  }
+
+  // Random enqueues and slices against a `VecDeque` of the same capacity.
+  fn check_against_deque(size: usize) {
+    let mut ring = Ring::new(size, 0);
+    let mut deque = std::iter::repeat(0).take(size).collect::<VecDeque<u32>>();
+    let mut push = |deque: &mut VecDeque<u32>, x| {
+      deque.push_back(x);
+      if deque.len() > size {
+        deque.pop_front();
+      }
+    };
+    
+    let mut seed = 12345 + size as u32;
+    let mut next = 1;
+    for _ in 0 .. 500 {
+      seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+      let n = (seed >> 16) as usize % (2 * size + 3);
+      
+      if seed & 1 == 0 {
+        let items = (next .. next + n as u32).collect::<Vec<_>>();
+        ring.extend_from_slice(&items);
+        for &x in items.iter() {
+          push(&mut deque, x);
+        }
+        next += n as u32;
+      } else {
+        ring.enqueue(next);
+        push(&mut deque, next);
+        next += 1;
+      }
+      
+      assert_eq!(&ring[..], &deque.iter().cloned().collect::<Vec<_>>()[..]);
+      assert_eq!(ring.len(), (next as usize - 1).min(size));
+    }
+  }
+
+  #[test]
+  fn any_capacity() {
+    for size in 0 .. 9 {
+      check_against_deque(size);
+    }
+    check_against_deque(100);
+  }
+
+  #[test]
+  fn accessors() {
+    let mut ring = Ring::new(3, 0);
+    assert!(ring.is_empty());
+    assert!(!ring.is_full());
+    assert_eq!(ring.capacity(), 3);
+    
+    ring.enqueue(1);
+    ring.enqueue(2);
+    assert_eq!(ring.len(), 2);
+    ring.extend_from_slice(&[3, 4, 5]);
+    assert!(ring.is_full());
+    assert_eq!(ring.len(), 3);
+    
+    ring.clear();
+    assert!(ring.is_empty());
+    assert_eq!(&ring[..], &[0, 0, 0]);
+    ring.enqueue(7);
+    assert_eq!(&ring[..], &[0, 0, 7]);
+  }
+}
@@ -0,0 +1,126 @@
+// https://en.wikipedia.org/wiki/Piano_key_frequencies
+pub fn from_piano_key(n: f32, a4: f32) -> f32 {
+  2.0_f32.powf((n - 49.0) / 12.0) * a4
+}
+
+pub fn to_piano_key(hz: f32, a4: f32) -> f32 {
+  12.0 * (hz / a4).log2() + 49.0
+}
+
+// MIDI note numbers are piano key numbers plus 20, 69 being A4.
+pub const MIDI_KEY_OFFSET: f32 = 20.0;
+
+pub fn from_midi_note(n: f32, a4: f32) -> f32 {
+  from_piano_key(n - MIDI_KEY_OFFSET, a4)
+}
+
+pub fn to_midi_note(hz: f32, a4: f32) -> f32 {
+  to_piano_key(hz, a4) + MIDI_KEY_OFFSET
+}
+
+// https://en.wikipedia.org/wiki/Mel_scale
+pub fn to_mel(hz: f32) -> f32 {
+  2595.0 * (1.0 + hz / 700.0).log10()
+}
+
+pub fn from_mel(mel: f32) -> f32 {
+  700.0 * (10.0_f32.powf(mel / 2595.0) - 1.0)
+}
+
+// https://en.wikipedia.org/wiki/Bark_scale, Traunmüller's formula.
+pub fn to_bark(hz: f32) -> f32 {
+  26.81 * hz / (1960.0 + hz) - 0.53
+}
+
+pub fn from_bark(bark: f32) -> f32 {
+  1960.0 * (bark + 0.53) / (26.28 - bark)
+}
+
+// Nearest key of a fractional piano key number, and the offset from it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NoteName {
+  pub letter: &'static str,
+  pub octave: i32,
+  pub cents: f32,
+}
+
+// From key 1, A0. Octaves start at C.
+const LETTERS: [&str; 12] = [
+  "A", "A#", "B", "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#",
+];
+
+pub fn key_to_note_name(key: f32) -> NoteName {
+  let nearest = key.round();
+  let n = nearest as i32;
+  NoteName {
+    letter: LETTERS[(n - 1).rem_euclid(12) as usize],
+    octave: (n + 8).div_euclid(12),
+    cents: 100.0 * (key - nearest),
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn key_49_is_the_tuning() {
+    for &a4 in [415.0, 432.0, 440.0, 442.0].iter() {
+      assert_eq!(from_piano_key(49.0, a4), a4);
+      assert_eq!(to_piano_key(a4, a4), 49.0);
+    }
+  }
+
+  #[test]
+  fn round_trips() {
+    for &a4 in [432.0, 440.0, 443.0].iter() {
+      for i in 0 .. 1000 {
+        let key = -10.0 + i as f32 * 0.13;
+        let back = to_piano_key(from_piano_key(key, a4), a4);
+        assert!((back - key).abs() < 1e-3, "{} {}", key, back);
+        
+        let note = key + MIDI_KEY_OFFSET;
+        let back = to_midi_note(from_midi_note(note, a4), a4);
+        assert!((back - note).abs() < 1e-3, "{} {}", note, back);
+      }
+    }
+    
+    assert!((from_midi_note(69.0, 440.0) - 440.0).abs() < 1e-3);
+    assert!((from_midi_note(60.0, 440.0) - 261.6256).abs() < 1e-2);
+  }
+
+  #[test]
+  fn mel_round_trip() {
+    assert!((to_mel(1000.0) - 1000.0).abs() < 0.1, "{}", to_mel(1000.0));
+    assert_eq!(to_mel(0.0), 0.0);
+    
+    for i in 0 .. 1000 {
+      let hz = i as f32 * 24.0;
+      let back = from_mel(to_mel(hz));
+      assert!((back - hz).abs() < 1e-3 * hz.max(1.0), "{} {}", hz, back);
+    }
+  }
+
+  #[test]
+  fn bark_of_1000_hz() {
+    assert!((to_bark(1000.0) - 8.5).abs() < 0.1, "{}", to_bark(1000.0));
+    assert!((from_bark(8.5) - 1000.0).abs() < 20.0, "{}", from_bark(8.5));
+  }
+
+  #[test]
+  fn a_keys() {
+    for octave in 0 .. 8 {
+      let key = 1 + 12 * octave;
+      let name = key_to_note_name(key as f32);
+      let expected = NoteName { letter: "A", octave: octave, cents: 0.0 };
+      assert_eq!(name, expected, "{}", key);
+    }
+    
+    let name = key_to_note_name(34.0 - 0.14);
+    assert_eq!((name.letter, name.octave), ("F#", 3));
+    assert!((name.cents + 14.0).abs() < 1e-3, "{}", name.cents);
+    assert_eq!(key_to_note_name(40.0).letter, "C");
+    assert_eq!(key_to_note_name(40.0).octave, 4);
+  }
+}
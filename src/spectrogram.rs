@@ -1,132 +1,5201 @@
-use rustfft::algorithm::Radix4;
+use std::cmp::Ordering;
+use std::fmt::Write;
+use std::mem;
+use std::ops::Range;
+use std::sync::Arc;
+
 use rustfft::FFT;
+use rustfft::FFTplanner;
 use rustfft::num_complex::Complex;
 use rustfft::num_traits::Zero;
 
 use crate::error::Error;
 use crate::ring::Ring;
+use crate::ring::SampleQueue;
 use crate::audio::Buffer;
 use crate::canvas::Line;
+use crate::canvas::LineData;
+use crate::canvas::PixelFormat;
+use crate::colormap::ColorMap;
+use crate::colormap::to_byte;
+use crate::filter::Decimation;
+use crate::filter::Decimator;
+use crate::filter::HighPass;
+use crate::filter::PreEmphasis;
+use crate::goertzel::goertzel;
+use crate::goertzel::goertzel_is_cheaper;
+use crate::meter::LevelMeter;
+use crate::meter::Levels;
+use crate::noise::NoiseFloor;
+use crate::onset::OnsetDetector;
+use crate::onset::OnsetEvent;
+use crate::reassign::REFINE_FACTOR;
+use crate::reassign::Reassigner;
+use crate::reassign::refined_len;
+use crate::scale::from_bark;
+use crate::scale::from_mel;
+use crate::scale::MIDI_KEY_OFFSET;
+use crate::scale::from_piano_key;
+use crate::scale::key_to_note_name;
+use crate::scale::NoteName;
+use crate::scale::to_bark;
+use crate::scale::to_mel;
+use crate::scale::to_piano_key;
+use crate::weighting::tilt_gain;
+use crate::weighting::Weighting;
+use crate::window::Window;
+
+
+const MIN_POWER: u32 = 2;
+const MAX_POWER: u32 = 20;
+
+// Duration of the average spectral flux onsets are compared to.
+const ONSET_WINDOW_MS: f32 = 1000.0;
+
+// Time the noise floor takes to rise to a louder noise, notes held for
+// less than that staying above it.
+const NOISE_WINDOW_S: f32 = 10.0;
+
+// Smallest maximum automatic gain normalizes to, so that silence stays
+// dark.
+const MIN_GAIN_REFERENCE: f32 = 1e-4;
+
+// Harmonics multiplied by the harmonic product spectrum, and the
+// magnitude relative to the maximum missing ones count as.
+const HPS_HARMONICS: usize = 5;
+const HPS_FLOOR: f32 = 0.01;
+
+// 4x4 Bayer matrix.
+const DITHER: [[u8;4];4] = [
+  [ 0,  8,  2, 10],
+  [12,  4, 14,  6],
+  [ 3, 11,  1,  9],
+  [15,  7, 13,  5],
+];
+
+// Width in keys of the crossfade between dual resolutions.
+const CROSSFADE_KEYS: f32 = 2.0;
+
+// Display levels the peak layer is compared to the frame at.
+const PEAK_LEVELS: f32 = 16.0;
+
+// Ratio to the noise gate where the soft knee ends.
+const GATE_KNEE: f32 = 2.0;
+
+// Values drawn into each pixel at most.
+const MAX_SUPERSAMPLE: u8 = 4;
+
+// Keys `zoom` and `pan` keep the range within by default, those of the
+// piano.
+const ZOOM_LIMITS: (f32,f32) = (1.0, 88.0);
+
+// Smallest span in keys `zoom` narrows the range to.
+pub const MIN_ZOOM_SPAN_KEYS: f32 = 3.0;
+
+// Key the tilt leaves untouched, A4.
+const TILT_PIVOT_KEY: f32 = 49.0;
+
+// Hops per FFT with `Quality::Reduced`.
+pub const REDUCED_HOP_FACTOR: usize = 4;
+
+// Level the bar of `Quality::Minimal` starts from.
+const LEVEL_BAR_FLOOR_DB: f32 = -60.0;
+
+// Values per frame of the history, evenly spread over the frequency
+// range.
+pub const HISTORY_BINS: usize = 128;
+
+// Weight of the newest call in the average duration of `process`.
+const PROCESS_TIME_WEIGHT: f32 = 0.05;
+
+const MIN_BUFFER_SIZE: usize = 1 << (MIN_POWER - 1);
+const MAX_BUFFER_SIZE: usize = 1 << (MAX_POWER - 1);
+
+
+// `Mid` is the same as `Mix`, `Side` is the half difference of the
+// channels, silent for mono input.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ChannelMode {
+  Left,
+  Right,
+  Mix,
+  Mid,
+  Side,
+}
+
+// Channels of multichannel input taking over the channel mode, `Mix`
+// averaging all of them.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChannelSelect {
+  Index(usize),
+  Mix,
+  MixOf(Vec<usize>),
+}
+
+// What `process` leaves in the buffer once the samples have been
+// analyzed. `Mute` is the default and silences the output.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OutputMode {
+  Mute,
+  Passthrough,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AmplitudeScale {
+  Boost(BoostCurve),
+  Decibel { floor_db: f32, ceil_db: f32 },
+}
+
+// Compression of linear magnitudes clamped to 0 to 1, all curves mapping
+// 0 to 0 and 1 to 1. `Rational(0)` and `Gamma(1)` leave magnitudes as is,
+// `Log` maps `floor` and below to 0.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BoostCurve {
+  Rational(f32),
+  Gamma(f32),
+  Log { floor: f32 },
+  Sigmoid { midpoint: f32, steepness: f32 },
+}
+
+// How positions along the line map to frequencies. `from`/`to` are
+// piano key numbers for `PianoKey` and Hz otherwise, `Mel` and `Bark`
+// spacing them evenly in mels and in critical bands.
+// A key range always spans whole keys, so `from == to` shows one key.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FrequencyScale {
+  PianoKey,
+  Mel,
+  LinearHz,
+  LogHz,
+  Bark,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Accumulate {
+  Average,
+  PeakHold,
+}
+
+// What is written to the alpha channel of pixel layouts that have one.
+// `Intensity` makes quiet bins transparent, for compositing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Alpha {
+  Opaque,
+  Intensity,
+}
+
+// How the bins of each frame are computed. `Goertzel` evaluates only the
+// bins within the frequency range, leaving the others at zero, which is
+// cheaper than the FFT for narrow ranges. `Auto` picks whichever is
+// cheaper for the range and FFT size.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Backend {
+  Fft,
+  Goertzel,
+  Auto,
+}
+
+// Cost of the analysis, for background tabs and low-power devices.
+// `Reduced` computes an FFT every `REDUCED_HOP_FACTOR` hops and keeps no
+// history. `Minimal` only meters the levels, drawn as a bar. The input
+// keeps filling the buffers whatever the quality, so that the FFTs start
+// again with valid data.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Quality {
+  Full,
+  Reduced,
+  Minimal,
+}
+
+// How the bins covered by a pixel are combined into a single value.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Reduce {
+  Mean,
+  Max,
+}
+
+// Bins below the gate are drawn as silence. `Auto` gates below the given
+// percentile of the frame's magnitudes, from 0 to 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NoiseGate {
+  Magnitude(f32),
+  Decibel(f32),
+  Auto(f32),
+}
+
+// Normalizes drawn frames to their maximum within the frequency range,
+// or to a running maximum rising and falling with the given time
+// constants so that single transients don't pump the display. `Hold`
+// rises at once and falls back over `hold_seconds`, keeping the relative
+// heights of consecutive frames.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AutoGain {
+  Frame,
+  Running { attack_s: f32, release_s: f32 },
+  Hold { hold_seconds: f32 },
+}
+
+// A second, smaller FFT on the newest `buffer_size` samples every `hop`
+// input samples, drawn above `crossover_key` for a better time resolution
+// in the high notes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DualResolution {
+  pub buffer_size: usize,
+  pub hop: usize,
+  pub crossover_key: f32,
+}
+
+// Markers blended over the pixels of each A and C key, as RGBA.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Grid {
+  pub octave_color: [u8;4],
+  pub c_color: [u8;4],
+}
+
+// Stops computing FFTs once every sample of `buffers` consecutive buffers
+// stayed below `threshold_db` dBFS, drawing the background instead. The
+// samples are still buffered so that the first loud buffer is analyzed
+// right away.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SilenceSkip {
+  pub threshold_db: f32,
+  pub buffers: usize,
+}
+
+// Bins start at the first non-DC bin of the FFT.
+pub struct Frame<'a> {
+  sum: &'a [f64],
+  n: usize,
+  fft_len: usize,
+  sample_rate: f32,
+  floor: &'a [f32],
+  strength: f32,
+}
+
+// Owned copy of the bins of a frame within the frequency range, for
+// exporting, with the noise floor of each bin under noise reduction. The
+// sample rate is the decimated one.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameData {
+  pub sample_rate: f32,
+  pub fft_size: usize,
+  pub decimation: usize,
+  pub bins: Vec<Bin>,
+  pub noise_floor: Option<Vec<f32>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bin {
+  pub hz: f32,
+  pub magnitude: f32,
+}
+
+// Strongest frequency of a frame, with the nearest piano key and the
+// offset from it in cents.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Peak {
+  pub frequency: f32,
+  pub magnitude: f32,
+  pub key: i32,
+  pub cents: f32,
+}
+
+// Fundamental frequency of a frame. The confidence is the geometric mean
+// of its harmonics relative to the strongest bin, from 0 to 1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PitchEstimate {
+  pub frequency: f32,
+  pub key: i32,
+  pub cents: f32,
+  pub confidence: f32,
+}
 
+// What is drawn at a frequency: the magnitude before the gate and the
+// gain, in dB relative to full scale, and the displayed value from 0 to
+// 1, with the nearest note.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Readout {
+  pub frequency: f32,
+  pub magnitude: f32,
+  pub db: f32,
+  pub value: f32,
+  pub note: NoteName,
+}
+
+// Descriptors of a frame: the mean frequency weighted by magnitude, the
+// frequency below which 85% of the magnitude lies, and the ratio of the
+// geometric to the arithmetic mean power, near 1 for noise.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Features {
+  pub centroid: f32,
+  pub rolloff: f32,
+  pub flatness: f32,
+}
+
+// Time constants of the exponential smoothing of drawn frames, rising
+// and falling magnitudes moving towards each new frame independently.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Smoothing {
+  pub attack_ms: f32,
+  pub release_ms: f32,
+}
+
+// Counters since the last `reset_stats`, wrapping around on overflow.
+// The average duration of `process` is only known with a clock. The hop
+// and the FFTs per second are the current ones, with the quality, no FFT
+// being computed while skipping silence or before the sample rate is
+// known.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stats {
+  pub samples: u64,
+  pub ffts: u64,
+  pub frames_drawn: u64,
+  pub non_finite: u64,
+  pub process_us: Option<f32>,
+  pub hop: usize,
+  pub ffts_per_second: f32,
+}
+
+// What a single `process` call did. Clipped and non-finite samples are
+// counted once per stereo frame, whatever the channel, the latter being
+// replaced with silence.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ProcessSummary {
+  pub samples: usize,
+  pub fft_frames: usize,
+  pub clipped: usize,
+  pub non_finite: usize,
+  pub peak: f32,
+}
+
+// Delay of the displayed spectrum behind the newest input sample.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Latency {
+  pub samples: usize,
+  pub seconds: f32,
+}
+
+// The callback only gets a copy of the frame, it can't reenter
+// `process`.
+struct FrameCallback {
+  every: usize,
+  count: usize,
+  f: Box<dyn FnMut(&FrameData) + Send>,
+}
+
+struct SmallAnalysis {
+  config: DualResolution,
+  fft: Arc<dyn FFT<f32>>,
+  input: Vec<Complex<f32>>,
+  output: Vec<Complex<f32>>,
+  coefficients: Vec<f32>,
+  freq_sum: Vec<f64>,
+  weights: Vec<f32>,
+  freq_n: usize,
+  hop_pos: usize,
+}
+
+// The right channel of the split-screen mode, the main analysis taking
+// the left one. Both share the FFT and the hop.
+struct RightChannel {
+  decimator: Decimator,
+  high_pass: Option<HighPass>,
+  pre_emphasis: Option<PreEmphasis>,
+  queue: Ring<Complex<f32>>,
+  output: Vec<Complex<f32>>,
+  freq_sum: Vec<f64>,
+  peaks: Vec<f64>,
+  smoothed: Vec<f64>,
+  refine: Option<Reassigner>,
+}
+
+// Frames captured when drawn, `HISTORY_BINS` values each from the oldest
+// to the newest.
+struct History {
+  frames: Ring<f32>,
+  row: Vec<f32>,
+}
+
+// Noise floors of the drawn frames, subtracted `strength` times.
+struct NoiseReduction {
+  strength: f32,
+  left: NoiseFloor,
+  right: NoiseFloor,
+  small: NoiseFloor,
+  updated_at: Option<u64>,
+}
+
+// How a frame is added to the sums, the same for both channels. `hop`
+// is the one the reassigner refines frequencies with.
+#[derive(Clone, Copy)]
+struct Accumulation<'a> {
+  hop: usize,
+  scale: f32,
+  weights: &'a [f32],
+  mode: Accumulate,
+  decay: Option<f64>,
+  peak_decay: f64,
+}
+
+// What `draw` computes once per line.
+struct Render<'a> {
+  frame: Frame<'a>,
+  small: Option<Frame<'a>>,
+  dx: f32,
+  gate: Option<f32>,
+  gain: f32,
+}
+
+// Snapshot of the configuration, for persisting it. Options set after
+// building are not part of it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SpectrogramConfig {
+  pub buffer_size: usize,
+  pub scale: FrequencyScale,
+  pub from: f32,
+  pub to: f32,
+  pub tuning_a4: f32,
+  pub amplitude_scale: AmplitudeScale,
+  pub window: Window,
+  pub hop: usize,
+  pub zero_pad_factor: usize,
+  pub decimation: Decimation,
+  pub channel_mode: ChannelMode,
+  pub output_mode: OutputMode,
+  pub color_map: ColorMap,
+  pub accumulate: Accumulate,
+  pub reduce: Reduce,
+  pub alpha: Alpha,
+}
 
+pub struct SpectrogramBuilder {
+  config: SpectrogramConfig,
+}
+
+// Buffers are all allocated up front, processing and drawing never
+// allocate so that they can run on the audio thread. Only the frames
+// copied for `on_frame` callbacks are allocated.
 pub struct Spectrogram {
-  from_key: f32,
-  to_key: f32,
-  boost: f32,
+  scale: FrequencyScale,
+  from: f32,
+  to: f32,
+  zoom_limits: (f32,f32),
+  tuning_a4: f32,
+  amplitude_scale: AmplitudeScale,
   sample_rate: f32,
+  window: Window,
+  hop: usize,
+  zero_pad_factor: usize,
+  decimation: Decimation,
+  channel_mode: ChannelMode,
+  output_mode: OutputMode,
+  color_map: ColorMap,
+  accumulate: Accumulate,
+  reduce: Reduce,
+  alpha: Alpha,
   
-  fft: Radix4<f32>,
+  fft: Arc<dyn FFT<f32>>,
+  decimator: Decimator,
+  high_pass: Option<HighPass>,
+  pre_emphasis: Option<PreEmphasis>,
   queue: Ring<Complex<f32>>,
   input: Vec<Complex<f32>>,
   output: Vec<Complex<f32>>,
+  coefficients: Vec<f32>,
   
-  freq_sum: Vec<f32>,
+  // Summed in f64 so that long averages don't drift.
+  freq_sum: Vec<f64>,
   freq_n: usize,
+  small: Option<SmallAnalysis>,
+  right: Option<RightChannel>,
+  hop_pos: usize,
+  sample_rate_change: Option<(f32,f32)>,
+  clamped_range: Option<(f32,f32)>,
+  averaging_window_ms: Option<f32>,
+  peak_threshold: f32,
+  pitch_threshold: f32,
+  noise_gate: Option<NoiseGate>,
+  grid: Option<Grid>,
+  brightness: f32,
+  contrast: f32,
+  invert: bool,
+  dither: bool,
+  hard_clip: bool,
+  channel: Option<ChannelSelect>,
+  supersample: u8,
+  frame_count: usize,
+  auto_gain: Option<AutoGain>,
+  gain_reference: f32,
+  gain_updated_at: u64,
+  onsets: OnsetDetector,
+  sample_count: u64,
+  left_meter: LevelMeter,
+  right_meter: LevelMeter,
+  silence_skip: Option<SilenceSkip>,
+  silent_buffers: usize,
+  frame_silent: bool,
+  stats: Stats,
+  summary: ProcessSummary,
+  clock: Option<Box<dyn Fn() -> f64 + Send>>,
+  on_frame: Option<FrameCallback>,
+  history: Option<History>,
+  weighting: Weighting,
+  tilt_db_per_octave: f32,
+  weights: Vec<f32>,
+  backend: Backend,
+  quality: Quality,
+  noise: Option<NoiseReduction>,
+  peak_decay_db_s: Option<f32>,
+  peaks: Vec<f64>,
+  drained: Vec<f32>,
+  smoothing: Option<Smoothing>,
+  smoothed: Vec<f64>,
+  smoothed_at: Option<u64>,
+  refine: Option<Reassigner>,
+  since_frame: usize,
 }
 
 
 impl Spectrogram {
+  // Out of range sizes are clamped, use `SpectrogramBuilder` to have
+  // the whole configuration validated instead.
   pub fn new( buffer_size_power: u32,
               from_key: f32,
               to_key: f32,
               boost: f32 ) -> Spectrogram {
     
-    let queue_size = 2_usize.pow(buffer_size_power);
-    let buffer_size = 2_usize.pow(buffer_size_power - 1);
+    Spectrogram::from_config( &SpectrogramBuilder::new()
+      .buffer_size_power(buffer_size_power.max(MIN_POWER).min(MAX_POWER))
+      .key_range(from_key, to_key)
+      .boost(boost)
+      .config )
+  }
+
+  pub fn try_new( buffer_size_power: u32,
+                  from_key: f32,
+                  to_key: f32,
+                  boost: f32 ) -> Result<Spectrogram,Error> {
+    
+    SpectrogramBuilder::new()
+      .buffer_size_power(buffer_size_power)
+      .key_range(from_key, to_key)
+      .boost(boost)
+      .build()
+  }
+
+  fn from_config(config: &SpectrogramConfig) -> Spectrogram {
+    let buffer_size = config.buffer_size;
+    let fft_size = buffer_size * config.zero_pad_factor;
     
     Spectrogram {
-      from_key: from_key,
-      to_key: to_key,
-      boost: boost,
+      scale: config.scale,
+      from: config.from,
+      to: config.to,
+      zoom_limits: ZOOM_LIMITS,
+      tuning_a4: config.tuning_a4,
+      amplitude_scale: config.amplitude_scale,
       sample_rate: 1.0,
+      window: config.window,
+      hop: config.hop,
+      zero_pad_factor: config.zero_pad_factor,
+      decimation: config.decimation,
+      channel_mode: config.channel_mode,
+      output_mode: config.output_mode,
+      color_map: config.color_map,
+      accumulate: config.accumulate,
+      reduce: config.reduce,
+      alpha: config.alpha,
       
-      fft: Radix4::new(buffer_size, false),
-      queue: Ring::new(queue_size, Complex::zero()),
-      input: vec![Complex::zero(); buffer_size],
-      output: vec![Complex::zero(); buffer_size],
+      fft: FFTplanner::new(false).plan_fft(fft_size),
+      decimator: Decimator::new(config.decimation),
+      high_pass: None,
+      pre_emphasis: None,
+      queue: Ring::new(buffer_size, Complex::zero()),
+      input: vec![Complex::zero(); fft_size],
+      output: vec![Complex::zero(); fft_size],
+      coefficients: config.window.coefficients(buffer_size),
       
-      freq_sum: vec![0.0; buffer_size / 2 - 1],
+      freq_sum: vec![0.0; fft_size / 2 - 1],
       freq_n: 0,
+      small: None,
+      right: None,
+      hop_pos: 0,
+      sample_rate_change: None,
+      clamped_range: None,
+      averaging_window_ms: None,
+      peak_threshold: 0.0,
+      pitch_threshold: 0.1,
+      noise_gate: None,
+      grid: None,
+      brightness: 0.0,
+      contrast: 1.0,
+      invert: false,
+      dither: false,
+      hard_clip: false,
+      channel: None,
+      supersample: 1,
+      frame_count: 0,
+      auto_gain: None,
+      gain_reference: MIN_GAIN_REFERENCE,
+      gain_updated_at: 0,
+      onsets: OnsetDetector::new(fft_size / 2 - 1, 2.0),
+      sample_count: 0,
+      left_meter: LevelMeter::new(300.0),
+      right_meter: LevelMeter::new(300.0),
+      silence_skip: None,
+      silent_buffers: 0,
+      frame_silent: false,
+      stats: Stats::default(),
+      summary: ProcessSummary::default(),
+      clock: None,
+      on_frame: None,
+      history: None,
+      weighting: Weighting::None,
+      tilt_db_per_octave: 0.0,
+      weights: Vec::new(),
+      backend: Backend::default(),
+      quality: Quality::default(),
+      noise: None,
+      peak_decay_db_s: None,
+      peaks: Vec::new(),
+      drained: Vec::new(),
+      smoothing: None,
+      smoothed: vec![0.0; fft_size / 2 - 1],
+      smoothed_at: None,
+      refine: None,
+      since_frame: 0,
     }
   }
-  
-  pub fn process(&mut self, buffer: Buffer) -> Result<(),Error> {
-    self.sample_rate = buffer.sample_rate / 2.0;
+
+  pub fn config(&self) -> SpectrogramConfig {
+    SpectrogramConfig {
+      buffer_size: self.buffer_size(),
+      scale: self.scale,
+      from: self.from,
+      to: self.to,
+      tuning_a4: self.tuning_a4,
+      amplitude_scale: self.amplitude_scale,
+      window: self.window,
+      hop: self.hop,
+      zero_pad_factor: self.zero_pad_factor,
+      decimation: self.decimation,
+      channel_mode: self.channel_mode,
+      output_mode: self.output_mode,
+      color_map: self.color_map,
+      accumulate: self.accumulate,
+      reduce: self.reduce,
+      alpha: self.alpha,
+    }
+  }
+
+  // Validates the whole configuration before applying any of it. A new
+  // buffer size, zero padding or decimation drops the buffered samples.
+  pub fn apply_config( &mut self,
+                       config: &SpectrogramConfig ) -> Result<(),Error> {
+    config.check()?;
     
-    for sample in buffer.data.iter_mut() {
-      self.queue.enqueue(Complex::new(*sample.left, 0.0));
+    if self.sample_rate != 1.0 {
+      let rate = self.sample_rate * self.decimation.factor() as f32;
+      let factor = config.decimation.factor() as f32;
+      check_nyquist(config.scale, config.to, rate / factor)?;
+    }
+    
+    if config.buffer_size != self.buffer_size()
+      || config.zero_pad_factor != self.zero_pad_factor
+      || config.decimation != self.decimation {
+        self.reallocate( config.buffer_size,
+                         config.zero_pad_factor,
+                         config.decimation );
+      }
+    
+    self.scale = config.scale;
+    self.from = config.from;
+    self.to = config.to;
+    self.tuning_a4 = config.tuning_a4;
+    self.amplitude_scale = config.amplitude_scale;
+    self.set_window(config.window);
+    self.set_hop(config.hop);
+    self.set_channel_mode(config.channel_mode);
+    self.output_mode = config.output_mode;
+    self.color_map = config.color_map;
+    self.set_accumulate(config.accumulate);
+    self.reduce = config.reduce;
+    self.alpha = config.alpha;
+    Ok(())
+  }
 
-      for (src,dst) in self.queue.chunks_exact(2)
-        .zip(self.input.iter_mut()) {
-          if let [a,b] = src {
-            *dst = (a + b) / 2.0;
-          }
-        }
-      
-      self.fft.process(&mut self.input, &mut self.output);
-      
-      for (bin,sum) in
-        self.output[1 .. self.output.len() / 2]
-        .iter().zip(self.freq_sum.iter_mut()) {
-          *sum += 2.0 * bin.norm() / self.output.len() as f32;
-        }
-      
-      self.freq_n += 1;
-      
-      *sample.left  = 0.0;
-      *sample.right = 0.0;
+  // Allocates the buffers for a new FFT size, keeping the other options.
+  // The dual resolution is dropped unless it is still smaller.
+  fn reallocate( &mut self,
+                 buffer_size: usize,
+                 zero_pad_factor: usize,
+                 decimation: Decimation ) {
+    let fft_size = buffer_size * zero_pad_factor;
+    
+    if self.sample_rate != 1.0 {
+      let rate = self.sample_rate * self.decimation.factor() as f32;
+      self.sample_rate = rate / decimation.factor() as f32;
+    }
+    
+    self.zero_pad_factor = zero_pad_factor;
+    self.decimation = decimation;
+    self.fft = FFTplanner::new(false).plan_fft(fft_size);
+    self.decimator = Decimator::new(decimation);
+    self.queue = Ring::new(buffer_size, Complex::zero());
+    self.input = vec![Complex::zero(); fft_size];
+    self.output = vec![Complex::zero(); fft_size];
+    self.coefficients = self.window.coefficients(buffer_size);
+    if self.refine.is_some() {
+      self.refine = Some(Reassigner::new(fft_size));
+    }
+    self.freq_sum = vec![0.0; self.bins_len()];
+    self.smoothed = vec![0.0; self.bins_len()];
+    self.resize_peaks();
+    self.onsets = OnsetDetector::new(fft_size / 2 - 1, self.onsets.threshold());
+    
+    if self.right.is_some() {
+      self.right = Some(self.right_channel());
+    }
+    
+    let dual = self.small.take().map(|small| small.config);
+    self.set_dual_resolution(dual).ok();
+    self.update_weights();
+    
+    self.clear_buffers();
+  }
+
+  pub fn window(&self) -> Window {
+    self.window
+  }
+
+  pub fn set_window(&mut self, window: Window) {
+    self.window = window;
+    self.update_window();
+  }
+
+  pub fn hop(&self) -> usize {
+    self.hop
+  }
+
+  // Number of input samples between two FFTs.
+  pub fn set_hop(&mut self, hop: usize) {
+    self.hop = hop.max(1);
+    self.hop_pos = self.hop_pos.min(self.hop - 1);
+  }
+
+  pub fn zero_pad_factor(&self) -> usize {
+    self.zero_pad_factor
+  }
+
+  pub fn decimation(&self) -> Decimation {
+    self.decimation
+  }
+
+  pub fn channel_mode(&self) -> ChannelMode {
+    self.channel_mode
+  }
+
+  pub fn set_channel_mode(&mut self, channel_mode: ChannelMode) {
+    if channel_mode != self.channel_mode {
+      self.channel_mode = channel_mode;
+      self.clear_accumulator();
     }
+  }
+
+  pub fn channel(&self) -> Option<&ChannelSelect> {
+    self.channel.as_ref()
+  }
 
+  // Analyzes the selected channels instead of the channel mode, only for
+  // the left side when the stereo is split. Input lacking any of them is
+  // rejected.
+  pub fn set_channel( &mut self,
+                      channel: Option<ChannelSelect> ) -> Result<(),Error> {
+    if let Some(ChannelSelect::MixOf(channels)) = channel.as_ref() {
+      if channels.is_empty() {
+        return Err(invalid("channels", 0.0));
+      }
+    }
+    
+    if channel != self.channel {
+      self.channel = channel;
+      self.clear_accumulator();
+    }
     Ok(())
   }
-  
-  pub fn draw_frame(&mut self, line: Line) -> Result<(),Error> {
-    if self.freq_n == 0 {
-      return Ok(());
+
+  pub fn output_mode(&self) -> OutputMode {
+    self.output_mode
+  }
+
+  pub fn set_output_mode(&mut self, output_mode: OutputMode) {
+    self.output_mode = output_mode;
+  }
+
+  pub fn hard_clip(&self) -> bool {
+    self.hard_clip
+  }
+
+  // Clips the analyzed samples to -1..1, the meters and the output being
+  // left as is.
+  pub fn set_hard_clip(&mut self, hard_clip: bool) {
+    self.hard_clip = hard_clip;
+  }
+
+  pub fn color_map(&self) -> ColorMap {
+    self.color_map
+  }
+
+  pub fn set_color_map(&mut self, color_map: ColorMap) {
+    self.color_map = color_map;
+  }
+
+  pub fn amplitude_scale(&self) -> AmplitudeScale {
+    self.amplitude_scale
+  }
+
+  pub fn set_amplitude_scale( &mut self,
+                              amplitude_scale: AmplitudeScale
+                            ) -> Result<(),Error> {
+    check_amplitude_scale(amplitude_scale)?;
+    self.amplitude_scale = amplitude_scale;
+    Ok(())
+  }
+
+  pub fn display_range_db(&self) -> Option<(f32,f32)> {
+    match self.amplitude_scale {
+      AmplitudeScale::Decibel { floor_db, ceil_db } => Some((floor_db, ceil_db)),
+      AmplitudeScale::Boost(_) => None,
+    }
+  }
+
+  // Draws magnitudes below `min_db` as the background and saturates those
+  // above `max_db`, replacing the boost.
+  pub fn set_display_range_db( &mut self,
+                               min_db: f32,
+                               max_db: f32 ) -> Result<(),Error> {
+    self.set_amplitude_scale( AmplitudeScale::Decibel {
+      floor_db: min_db,
+      ceil_db: max_db,
+    })
+  }
+
+  pub fn set_boost(&mut self, boost: f32) -> Result<(),Error> {
+    let curve = BoostCurve::Rational(boost);
+    self.set_amplitude_scale(AmplitudeScale::Boost(curve))
+  }
+
+  pub fn frequency_scale(&self) -> FrequencyScale {
+    self.scale
+  }
+
+  pub fn frequency_range(&self) -> (f32,f32) {
+    (self.from, self.to)
+  }
+
+  pub fn set_frequency_range( &mut self,
+                              scale: FrequencyScale,
+                              from: f32,
+                              to: f32 ) -> Result<(),Error> {
+    check_range(scale, from, to)?;
+
+    // The sample rate is only known once the first buffer arrived.
+    if self.sample_rate > 1.0 {
+      check_nyquist(scale, to, self.sample_rate)?;
     }
 
-    let keys = self.to_key - self.from_key + 1.0;
+    self.scale = scale;
+    self.from = from;
+    self.to = to;
+    Ok(())
+  }
+
+  pub fn set_key_range( &mut self,
+                        from_key: f32,
+                        to_key: f32 ) -> Result<(),Error> {
+    self.set_frequency_range(FrequencyScale::PianoKey, from_key, to_key)
+  }
+
+  // Same as `set_key_range` with MIDI note numbers.
+  pub fn set_midi_range(&mut self, from: u8, to: u8) -> Result<(),Error> {
+    self.set_key_range( from as f32 - MIDI_KEY_OFFSET,
+                        to as f32 - MIDI_KEY_OFFSET )
+  }
+
+  pub fn set_frequency_range_hz( &mut self,
+                                 from_hz: f32,
+                                 to_hz: f32 ) -> Result<(),Error> {
+    self.set_frequency_range(FrequencyScale::LogHz, from_hz, to_hz)
+  }
+
+  pub fn zoom_limits(&self) -> (f32,f32) {
+    self.zoom_limits
+  }
+
+  // Keys `zoom` and `pan` keep the range within, spanning at least
+  // `MIN_ZOOM_SPAN_KEYS`.
+  pub fn set_zoom_limits( &mut self,
+                          from_key: f32,
+                          to_key: f32 ) -> Result<(),Error> {
+    if !from_key.is_finite() {
+      return Err(invalid("from_key", from_key));
+    }
+    if !(to_key - from_key >= MIN_ZOOM_SPAN_KEYS) || !to_key.is_finite() {
+      return Err(invalid("to_key", to_key));
+    }
     
-    for pixel in line.data.iter_mut() {
-      let x = pixel.x as f32 / (line.len - 1) as f32;
-      let f = from_piano_key(
-        x * keys + self.from_key - 0.5
-      );
-      
-      let i = (f * self.output.len() as f32 / self.sample_rate - 1.0)
-        .max(0.0).min(self.freq_sum.len() as f32 - 1.0);
-      
-      let i0 = i.floor() as usize;
-      let i1 = i.ceil()  as usize;
-      let di = i.fract();
+    self.zoom_limits = (from_key, to_key);
+    Ok(())
+  }
 
-      let v0 = self.freq_sum[i0] / self.freq_n as f32;
-      let v1 = self.freq_sum[i1] / self.freq_n as f32;
+  // Narrows the key range `factor` times around `center_key`, which stays
+  // where it is drawn, or widens it for a factor below 1. Fails unless
+  // the range is in piano keys.
+  pub fn zoom(&mut self, center_key: f32, factor: f32) -> Result<(),Error> {
+    self.check_zoomable()?;
+    if !center_key.is_finite() {
+      return Err(invalid("center_key", center_key));
+    }
+    if !(factor > 0.0) || !factor.is_finite() {
+      return Err(invalid("factor", factor));
+    }
+    
+    let span = self.to - self.from;
+    let new_span = (span / factor).max(MIN_ZOOM_SPAN_KEYS);
+    // Where the center is in the range, so that it stays there.
+    let t = if span > 0.0 {
+      ((center_key - self.from) / span).max(0.0).min(1.0)
+    } else {
+      0.5
+    };
+    
+    let from = center_key - t * new_span;
+    self.fit_key_range(from, from + new_span);
+    Ok(())
+  }
 
-      let v = boost(v0 * (1.0 - di) + v1 * di, self.boost);
+  // Moves the key range up by `delta_keys`, or down if negative, as far
+  // as the zoom limits. Fails unless the range is in piano keys.
+  pub fn pan(&mut self, delta_keys: f32) -> Result<(),Error> {
+    self.check_zoomable()?;
+    if !delta_keys.is_finite() {
+      return Err(invalid("delta_keys", delta_keys));
+    }
+    
+    self.fit_key_range(self.from + delta_keys, self.to + delta_keys);
+    Ok(())
+  }
 
-      let c = ((1.0 - v) * u8::max_value() as f32) as u8;
-      
-      *pixel.r = c;
-      *pixel.g = c;
-      *pixel.b = c;
+  fn check_zoomable(&self) -> Result<(),Error> {
+    match self.scale {
+      FrequencyScale::PianoKey => Ok(()),
+      // The discriminant, there being no better number for a scale.
+      scale => Err(invalid("scale", scale as u8 as f32)),
     }
+  }
+
+  // Sets the key range, shifted into the zoom limits and shrunk to them if
+  // wider, keeping its span otherwise.
+  fn fit_key_range(&mut self, from: f32, to: f32) {
+    let (min, max) = self.zoom_limits;
+    let span = (to - from).max(MIN_ZOOM_SPAN_KEYS).min(max - min);
+    let from = from.max(min).min(max - span);
     
-    self.freq_n = 0;
-    for sum in self.freq_sum.iter_mut() {
-      *sum = 0.0;
+    self.from = from;
+    self.to = from + span;
+  }
+
+  pub fn tuning_a4(&self) -> f32 {
+    self.tuning_a4
+  }
+
+  pub fn set_tuning_a4(&mut self, tuning_a4: f32) -> Result<(),Error> {
+    check_tuning_a4(tuning_a4)?;
+    self.tuning_a4 = tuning_a4;
+    if self.tilt_db_per_octave != 0.0 {
+      self.update_weights();
+    }
+    Ok(())
+  }
+
+  pub fn accumulate(&self) -> Accumulate {
+    self.accumulate
+  }
+
+  pub fn set_accumulate(&mut self, accumulate: Accumulate) {
+    if accumulate != self.accumulate {
+      self.accumulate = accumulate;
+      self.clear_accumulator();
+    }
+  }
+
+  pub fn reduce(&self) -> Reduce {
+    self.reduce
+  }
+
+  pub fn set_reduce(&mut self, reduce: Reduce) {
+    self.reduce = reduce;
+  }
+
+  pub fn supersample(&self) -> u8 {
+    self.supersample
+  }
+
+  // Values evaluated per pixel and averaged before the color map, from 1
+  // to `MAX_SUPERSAMPLE`, smoothing lines spanning many keys per pixel.
+  pub fn set_supersample(&mut self, supersample: u8) -> Result<(),Error> {
+    if supersample < 1 || supersample > MAX_SUPERSAMPLE {
+      return Err(invalid("supersample", supersample as f32));
+    }
+    self.supersample = supersample;
+    Ok(())
+  }
+
+  pub fn alpha(&self) -> Alpha {
+    self.alpha
+  }
+
+  pub fn set_alpha(&mut self, alpha: Alpha) {
+    self.alpha = alpha;
+  }
+
+  pub fn averaging_window_ms(&self) -> Option<f32> {
+    self.averaging_window_ms
+  }
+
+  // With a window, averaged frames are an exponential moving average over
+  // that duration instead of the average since the last draw.
+  pub fn set_averaging_window_ms( &mut self,
+                                  window_ms: Option<f32>
+                                ) -> Result<(),Error> {
+    if let Some(ms) = window_ms {
+      if !(ms > 0.0) {
+        return Err(invalid("averaging_window_ms", ms));
+      }
     }
     
+    self.averaging_window_ms = window_ms;
+    self.clear_accumulator();
     Ok(())
   }
-}
 
+  pub fn peak_threshold(&self) -> f32 {
+    self.peak_threshold
+  }
+
+  // Linear magnitude below which `peak` finds nothing.
+  pub fn set_peak_threshold(&mut self, threshold: f32) -> Result<(),Error> {
+    if !(threshold >= 0.0) {
+      return Err(invalid("peak_threshold", threshold));
+    }
+    
+    self.peak_threshold = threshold;
+    Ok(())
+  }
 
-fn boost(value: f32, by: f32) -> f32 {
-  ((by + 1.0) * value) / (by * value + 1.0)
-}
+  pub fn noise_gate(&self) -> Option<NoiseGate> {
+    self.noise_gate
+  }
 
-// https://en.wikipedia.org/wiki/Piano_key_frequencies
-fn from_piano_key(n: f32) -> f32 {
-  2.0_f32.powf((n - 49.0) / 12.0) * 440.0
+  pub fn set_noise_gate( &mut self,
+                         noise_gate: Option<NoiseGate> ) -> Result<(),Error> {
+    match noise_gate {
+      Some(NoiseGate::Magnitude(m)) if !(m >= 0.0) =>
+        return Err(invalid("noise_gate", m)),
+      Some(NoiseGate::Decibel(db)) if db.is_nan() =>
+        return Err(invalid("noise_gate", db)),
+      Some(NoiseGate::Auto(p)) if !(p >= 0.0 && p <= 1.0) =>
+        return Err(invalid("noise_gate", p)),
+      _ => (),
+    }
+    
+    self.noise_gate = noise_gate;
+    Ok(())
+  }
+
+  pub fn grid(&self) -> Option<Grid> {
+    self.grid
+  }
+
+  pub fn set_grid(&mut self, grid: Option<Grid>) {
+    self.grid = grid;
+  }
+
+  pub fn brightness(&self) -> f32 {
+    self.brightness
+  }
+
+  // Offset added to displayed values, which range from 0 to 1.
+  pub fn set_brightness(&mut self, brightness: f32) -> Result<(),Error> {
+    if !brightness.is_finite() {
+      return Err(invalid("brightness", brightness));
+    }
+    
+    self.brightness = brightness;
+    Ok(())
+  }
+
+  pub fn contrast(&self) -> f32 {
+    self.contrast
+  }
+
+  // Scales displayed values around 0.5.
+  pub fn set_contrast(&mut self, contrast: f32) -> Result<(),Error> {
+    if !(contrast >= 0.0 && contrast.is_finite()) {
+      return Err(invalid("contrast", contrast));
+    }
+    
+    self.contrast = contrast;
+    Ok(())
+  }
+
+  pub fn invert(&self) -> bool {
+    self.invert
+  }
+
+  pub fn set_invert(&mut self, invert: bool) {
+    self.invert = invert;
+  }
+
+  pub fn dither(&self) -> bool {
+    self.dither
+  }
+
+  // Ordered dithering of the colors, against banding in smooth gradients.
+  // Consecutive frames use consecutive rows of the dither matrix.
+  pub fn set_dither(&mut self, dither: bool) {
+    self.dither = dither;
+  }
+
+  pub fn auto_gain(&self) -> Option<AutoGain> {
+    self.auto_gain
+  }
+
+  pub fn set_auto_gain( &mut self,
+                        auto_gain: Option<AutoGain> ) -> Result<(),Error> {
+    match auto_gain {
+      Some(AutoGain::Running { attack_s, .. }) if !(attack_s > 0.0) =>
+        return Err(invalid("attack_s", attack_s)),
+      Some(AutoGain::Running { release_s, .. }) if !(release_s > 0.0) =>
+        return Err(invalid("release_s", release_s)),
+      Some(AutoGain::Hold { hold_seconds }) if !(hold_seconds > 0.0) =>
+        return Err(invalid("hold_seconds", hold_seconds)),
+      _ => (),
+    }
+    
+    self.auto_gain = auto_gain;
+    self.gain_reference = MIN_GAIN_REFERENCE;
+    self.gain_updated_at = self.sample_count;
+    Ok(())
+  }
+
+  pub fn high_pass(&self) -> Option<f32> {
+    self.high_pass.as_ref().map(|h| h.cutoff_hz())
+  }
+
+  // Removes DC offsets from the analyzed signal, usually with a cutoff
+  // of 20 Hz.
+  pub fn set_high_pass(&mut self, cutoff_hz: Option<f32>) -> Result<(),Error> {
+    if let Some(hz) = cutoff_hz {
+      if !(hz > 0.0) {
+        return Err(invalid("high_pass", hz));
+      }
+    }
+    
+    let rate = self.sample_rate * self.decimation.factor() as f32;
+    self.high_pass = cutoff_hz.map(|hz| HighPass::new(hz, rate));
+    if let Some(right) = self.right.as_mut() {
+      right.high_pass = cutoff_hz.map(|hz| HighPass::new(hz, rate));
+    }
+    Ok(())
+  }
+
+  pub fn pre_emphasis(&self) -> Option<f32> {
+    self.pre_emphasis.as_ref().map(|p| p.coefficient())
+  }
+
+  // Emphasizes the analyzed signal, not the output, usually with a
+  // coefficient of 0.97.
+  pub fn set_pre_emphasis( &mut self,
+                           coefficient: Option<f32> ) -> Result<(),Error> {
+    if let Some(a) = coefficient {
+      if !(a >= 0.0 && a < 1.0) {
+        return Err(invalid("pre_emphasis", a));
+      }
+    }
+    
+    self.pre_emphasis = coefficient.map(PreEmphasis::new);
+    if let Some(right) = self.right.as_mut() {
+      right.pre_emphasis = coefficient.map(PreEmphasis::new);
+    }
+    Ok(())
+  }
+
+  pub fn split_stereo(&self) -> bool {
+    self.right.is_some()
+  }
+
+  // Draws the left channel on the left half of the line, frequencies
+  // increasing towards the center, and the right channel mirrored on the
+  // right half. The channel mode is ignored meanwhile.
+  pub fn set_split_stereo(&mut self, split_stereo: bool) {
+    if split_stereo == self.split_stereo() {
+      return;
+    }
+
+    self.right = if split_stereo { Some(self.right_channel()) } else { None };
+    self.clear_accumulator();
+  }
+
+  fn right_channel(&self) -> RightChannel {
+    let rate = self.sample_rate * self.decimation.factor() as f32;
+    RightChannel {
+      decimator: Decimator::new(self.decimation),
+      high_pass: self.high_pass.as_ref()
+        .map(|h| HighPass::new(h.cutoff_hz(), rate)),
+      pre_emphasis: self.pre_emphasis.as_ref()
+        .map(|p| PreEmphasis::new(p.coefficient())),
+      queue: Ring::new(self.buffer_size(), Complex::zero()),
+      output: vec![Complex::zero(); self.output.len()],
+      freq_sum: vec![0.0; self.freq_sum.len()],
+      peaks: vec![0.0; self.peaks.len()],
+      smoothed: vec![0.0; self.freq_sum.len()],
+      refine: self.refine.as_ref().map(|_| Reassigner::new(self.output.len())),
+    }
+  }
+
+  pub fn dual_resolution(&self) -> Option<DualResolution> {
+    self.small.as_ref().map(|small| small.config)
+  }
+
+  pub fn set_dual_resolution( &mut self,
+                              config: Option<DualResolution>
+                            ) -> Result<(),Error> {
+    let config = match config {
+      Some(config) => config,
+      None => {
+        self.small = None;
+        return Ok(());
+      },
+    };
+    
+    if config.buffer_size < MIN_BUFFER_SIZE
+      || config.buffer_size >= self.buffer_size() {
+        return Err(invalid("buffer_size", config.buffer_size as f32));
+      }
+    if config.hop == 0 {
+      return Err(invalid("hop", 0.0));
+    }
+    if !config.crossover_key.is_finite() {
+      return Err(invalid("crossover_key", config.crossover_key));
+    }
+    
+    let fft_size = config.buffer_size * self.zero_pad_factor;
+    self.small = Some( SmallAnalysis {
+      config: config,
+      fft: FFTplanner::new(false).plan_fft(fft_size),
+      input: vec![Complex::zero(); fft_size],
+      output: vec![Complex::zero(); fft_size],
+      coefficients: self.window.coefficients(config.buffer_size),
+      freq_sum: vec![0.0; fft_size / 2 - 1],
+      weights: Vec::new(),
+      freq_n: 0,
+      hop_pos: 0,
+    });
+    self.update_weights();
+    Ok(())
+  }
+
+  pub fn onset_threshold(&self) -> f32 {
+    self.onsets.threshold()
+  }
+
+  // Ratio to the average spectral flux above which a frame is an onset.
+  pub fn set_onset_threshold(&mut self, threshold: f32) -> Result<(),Error> {
+    if !(threshold > 0.0) {
+      return Err(invalid("onset_threshold", threshold));
+    }
+    
+    self.onsets.set_threshold(threshold);
+    Ok(())
+  }
+
+  // Onsets detected since the last call.
+  pub fn take_onsets(&mut self) -> Vec<OnsetEvent> {
+    self.onsets.take()
+  }
+
+  // Levels of the analyzed channel, the loudest of both channels when
+  // mixing them.
+  pub fn levels(&self) -> Levels {
+    match self.channel_mode {
+      ChannelMode::Left  => self.left_meter.levels(),
+      ChannelMode::Right => self.right_meter.levels(),
+      ChannelMode::Mix | ChannelMode::Mid | ChannelMode::Side =>
+        self.left_meter.levels().max(self.right_meter.levels()),
+    }
+  }
+
+  pub fn level_window_ms(&self) -> f32 {
+    self.left_meter.window_ms()
+  }
+
+  // Duration the RMS level is averaged over.
+  pub fn set_level_window_ms(&mut self, window_ms: f32) -> Result<(),Error> {
+    if !(window_ms > 0.0) {
+      return Err(invalid("level_window_ms", window_ms));
+    }
+    
+    let rate = self.sample_rate * self.decimation.factor() as f32;
+    self.left_meter.set_window_ms(window_ms, rate);
+    self.right_meter.set_window_ms(window_ms, rate);
+    Ok(())
+  }
+
+  pub fn silence_skip(&self) -> Option<SilenceSkip> {
+    self.silence_skip
+  }
+
+  pub fn set_silence_skip( &mut self,
+                           silence_skip: Option<SilenceSkip>
+                         ) -> Result<(),Error> {
+    if let Some(skip) = silence_skip {
+      if skip.threshold_db.is_nan() {
+        return Err(invalid("threshold_db", skip.threshold_db));
+      }
+      if skip.buffers == 0 {
+        return Err(invalid("buffers", 0.0));
+      }
+    }
+    
+    self.silence_skip = silence_skip;
+    self.silent_buffers = 0;
+    Ok(())
+  }
+
+  // Whether the last frame drawn was the background of a skipped
+  // silence.
+  pub fn silence_skipped(&self) -> bool {
+    self.frame_silent
+  }
+
+  pub fn smoothing(&self) -> Option<Smoothing> {
+    self.smoothing
+  }
+
+  pub fn set_smoothing( &mut self,
+                        smoothing: Option<Smoothing> ) -> Result<(),Error> {
+    if let Some(Smoothing { attack_ms, release_ms }) = smoothing {
+      if !(attack_ms > 0.0) {
+        return Err(invalid("attack_ms", attack_ms));
+      }
+      if !(release_ms > 0.0) {
+        return Err(invalid("release_ms", release_ms));
+      }
+    }
+    
+    if smoothing.is_none() {
+      self.smoothed_at = None;
+    }
+    self.smoothing = smoothing;
+    Ok(())
+  }
+
+  // Strength of the noise reduction, if enabled.
+  pub fn noise_reduction(&self) -> Option<f32> {
+    self.noise.as_ref().map(|noise| noise.strength)
+  }
+
+  // Subtracts `strength` times the noise floor of each bin from the drawn
+  // magnitudes, estimated over the last `NOISE_WINDOW_S` seconds of drawn
+  // frames. The floor is the average noise level, more than 1 also takes
+  // away most of its fluctuations.
+  pub fn set_noise_reduction( &mut self,
+                              enabled: bool,
+                              strength: f32 ) -> Result<(),Error> {
+    if !(strength >= 0.0 && strength.is_finite()) {
+      return Err(invalid("strength", strength));
+    }
+    
+    match (enabled, self.noise.as_mut()) {
+      (false, _) => self.noise = None,
+      (true, Some(noise)) => noise.strength = strength,
+      (true, None) => self.noise = Some( NoiseReduction {
+        strength: strength,
+        left: NoiseFloor::new(0, NOISE_WINDOW_S),
+        right: NoiseFloor::new(0, NOISE_WINDOW_S),
+        small: NoiseFloor::new(0, NOISE_WINDOW_S),
+        updated_at: None,
+      }),
+    }
+    Ok(())
+  }
+
+  // Decay of the peak layer in dB per second, if enabled.
+  pub fn peak_layer(&self) -> Option<f32> {
+    self.peak_decay_db_s
+  }
+
+  // Holds the peak of each bin alongside the accumulator, falling by
+  // `decay_db_s` dB per second, for `draw_frame_layers`. The peaks are
+  // those of the main FFT, also under dual resolution.
+  pub fn set_peak_layer( &mut self,
+                         decay_db_s: Option<f32> ) -> Result<(),Error> {
+    if let Some(decay) = decay_db_s {
+      if !(decay >= 0.0 && decay.is_finite()) {
+        return Err(invalid("decay_db_s", decay));
+      }
+    }
+    
+    let resize = decay_db_s.is_some() != self.peak_decay_db_s.is_some();
+    self.peak_decay_db_s = decay_db_s;
+    if resize {
+      self.resize_peaks();
+    }
+    Ok(())
+  }
+
+  // One peak per bin with a peak layer, none otherwise.
+  fn resize_peaks(&mut self) {
+    let len = match self.peak_decay_db_s {
+      Some(_) => self.freq_sum.len(),
+      None => 0,
+    };
+    
+    self.peaks = vec![0.0; len];
+    if let Some(right) = self.right.as_mut() {
+      right.peaks = vec![0.0; len];
+    }
+  }
+
+  pub fn refine(&self) -> bool {
+    self.refine.is_some()
+  }
+
+  // Draws the magnitude of each bin at its instantaneous frequency rather
+  // than at its center, on `REFINE_FACTOR` times more bins, separating
+  // the low notes. Works best with hops of at most a quarter of the
+  // buffer.
+  pub fn set_refine(&mut self, refine: bool) {
+    if refine == self.refine() {
+      return;
+    }
+    
+    let fft_size = self.output.len();
+    self.refine = if refine { Some(Reassigner::new(fft_size)) } else { None };
+    
+    let len = self.bins_len();
+    self.freq_sum = vec![0.0; len];
+    self.smoothed = vec![0.0; len];
+    self.smoothed_at = None;
+    if let Some(right) = self.right.as_mut() {
+      right.freq_sum = vec![0.0; len];
+      right.smoothed = vec![0.0; len];
+      right.refine = if refine { Some(Reassigner::new(fft_size)) } else { None };
+    }
+    self.resize_peaks();
+    self.update_weights();
+    self.clear_accumulator();
+  }
+
+  pub fn weighting(&self) -> Weighting {
+    self.weighting
+  }
+
+  // Weights the magnitudes of each FFT by a sound level meter response,
+  // playing down the rumble and the highest frequencies.
+  pub fn set_weighting(&mut self, weighting: Weighting) {
+    if weighting != self.weighting {
+      self.weighting = weighting;
+      self.update_weights();
+      self.clear_accumulator();
+    }
+  }
+
+  pub fn tilt_db_per_octave(&self) -> f32 {
+    self.tilt_db_per_octave
+  }
+
+  // Raises the magnitudes by `tilt_db_per_octave` per octave above A4 and
+  // lowers them below, making up for the fall of most spectra with
+  // frequency. It goes into the weights, costing nothing per FFT.
+  pub fn set_tilt_db_per_octave( &mut self,
+                                 tilt_db_per_octave: f32 ) -> Result<(),Error> {
+    if !tilt_db_per_octave.is_finite() {
+      return Err(invalid("tilt_db_per_octave", tilt_db_per_octave));
+    }
+    
+    if tilt_db_per_octave != self.tilt_db_per_octave {
+      self.tilt_db_per_octave = tilt_db_per_octave;
+      self.update_weights();
+      self.clear_accumulator();
+    }
+    Ok(())
+  }
+
+  // The weighting and the tilt at the frequencies of the bins, which move
+  // with the decimated sample rate and the FFT sizes.
+  fn update_weights(&mut self) {
+    let weighting = self.weighting;
+    let tilt = self.tilt_db_per_octave;
+    let pivot_hz = from_piano_key(TILT_PIVOT_KEY, self.tuning_a4);
+    let rate = self.sample_rate;
+    
+    let fft_len = self.frame_fft_len() as f32;
+    self.weights = weights(weighting, tilt, pivot_hz, self.bins_len(), |bin| {
+      (bin + 1) as f32 * rate / fft_len
+    });
+    
+    if let Some(small) = self.small.as_mut() {
+      let fft_len = small.output.len() as f32;
+      small.weights = weights(weighting, tilt, pivot_hz, small.freq_sum.len(), |bin| {
+        (bin + 1) as f32 * rate / fft_len
+      });
+    }
+  }
+
+  pub fn backend(&self) -> Backend {
+    self.backend
+  }
+
+  // The bins outside the frequency range stay at zero with the Goertzel
+  // backend, so the pitch doesn't see the harmonics above the range.
+  pub fn set_backend(&mut self, backend: Backend) {
+    self.backend = backend;
+  }
+
+  pub fn quality(&self) -> Quality {
+    self.quality
+  }
+
+  // Takes effect from the next sample. The frame is cleared for the level
+  // bar of `Minimal`, and the refinement starts over after it, the FFTs
+  // not being contiguous.
+  pub fn set_quality(&mut self, quality: Quality) {
+    if quality == self.quality {
+      return;
+    }
+    
+    if quality == Quality::Minimal {
+      self.clear_accumulator();
+    } else if self.quality == Quality::Minimal {
+      let right = self.right.as_mut().and_then(|right| right.refine.as_mut());
+      for refine in self.refine.iter_mut().chain(right) {
+        refine.reset();
+      }
+    }
+    self.quality = quality;
+  }
+
+  // Input samples between two FFTs with the quality.
+  fn effective_hop(&self) -> usize {
+    match self.quality {
+      Quality::Reduced => self.hop * REDUCED_HOP_FACTOR,
+      Quality::Full | Quality::Minimal => self.hop,
+    }
+  }
+
+  // Bins of the FFT the frequency range is drawn from, `None` when the
+  // FFT computes them all. There are a few more around the range for the
+  // interpolation between bins and the refinement.
+  fn goertzel_bins(&self) -> Option<Range<usize>> {
+    let fft_size = self.output.len();
+    let bin_hz = self.sample_rate / fft_size as f32;
+    let margin = 2;
+    
+    let from = (self.frequency_at(0.0) / bin_hz).floor() as usize;
+    let to = (self.frequency_at(1.0) / bin_hz).ceil() as usize;
+    let bins = from.saturating_sub(margin).max(1)
+      .. (to + margin + 1).min(fft_size / 2);
+    
+    let samples = self.coefficients.len();
+    let cheaper = goertzel_is_cheaper(bins.len(), samples, fft_size);
+    match self.backend {
+      Backend::Goertzel => Some(bins),
+      Backend::Auto if cheaper => Some(bins),
+      _ => None,
+    }
+  }
+
+  // Number of bins of the frames.
+  fn bins_len(&self) -> usize {
+    match self.refine {
+      Some(_) => refined_len(self.output.len()),
+      None => self.output.len() / 2 - 1,
+    }
+  }
+
+  // Frequency resolution of the frames, as an FFT size.
+  fn frame_fft_len(&self) -> usize {
+    match self.refine {
+      Some(_) => REFINE_FACTOR * self.output.len(),
+      None => self.output.len(),
+    }
+  }
+
+  pub fn stats(&self) -> Stats {
+    let analyzing = self.quality != Quality::Minimal
+      && self.sample_rate != 1.0
+      && !self.skipping_silence();
+    let rate = self.sample_rate * self.decimation.factor() as f32;
+    
+    Stats {
+      hop: self.effective_hop(),
+      ffts_per_second: if analyzing { rate / self.effective_hop() as f32 } else { 0.0 },
+      ..self.stats
+    }
+  }
+
+  pub fn reset_stats(&mut self) {
+    self.stats = Stats::default();
+  }
+
+  // Times `process` with `clock`, returning microseconds, such as
+  // `Date.now() * 1000`. The clock must be `Send` like the spectrogram,
+  // so it can't capture JS objects.
+  pub fn set_clock(&mut self, clock: Option<Box<dyn Fn() -> f64 + Send>>) {
+    self.clock = clock;
+    self.stats.process_us = None;
+  }
+
+  // Center frequency of a bin of `frame`, the first one being above DC,
+  // at the current sample rate and decimation.
+  pub fn bin_to_hz(&self, bin: usize) -> f32 {
+    (bin + 1) as f32 * self.sample_rate / self.frame_fft_len() as f32
+  }
+
+  // Fractional piano key of a frequency with the current tuning.
+  pub fn hz_to_key(&self, hz: f32) -> f32 {
+    to_piano_key(hz, self.tuning_a4)
+  }
+
+  // Input samples analyzed since the last `reset`.
+  pub fn sample_count(&self) -> u64 {
+    self.sample_count
+  }
+
+  // Sample rate of the input, unknown until the first buffer.
+  pub fn sample_rate(&self) -> Option<f32> {
+    if self.sample_rate == 1.0 {
+      None
+    } else {
+      Some(self.sample_rate * self.decimation.factor() as f32)
+    }
+  }
+
+  // From the newest input sample to the center of the analyzed samples,
+  // plus half a hop and the averaging window on average. `None` until
+  // the sample rate is known.
+  pub fn latency(&self) -> Option<Latency> {
+    if self.sample_rate == 1.0 {
+      return None;
+    }
+    
+    let factor = self.decimation.factor();
+    let rate = self.sample_rate * factor as f32;
+    let window = match (self.accumulate, self.averaging_window_ms) {
+      (Accumulate::Average, Some(ms)) => (ms / 1000.0 * rate) as usize,
+      _ => 0,
+    };
+    
+    let samples = self.buffer_size() * factor / 2
+      + self.decimation.delay()
+      + self.effective_hop() / 2
+      + window;
+    
+    Some( Latency {
+      samples: samples,
+      seconds: samples as f32 / rate,
+    })
+  }
+
+  pub fn buffer_size(&self) -> usize {
+    self.input.len() / self.zero_pad_factor
+  }
+
+  // Changes the buffer size to 2^(buffer_size_power - 1) keeping the
+  // newest samples, so that a smaller FFT goes on right away and a larger
+  // one once it has enough of them. The accumulator is cleared.
+  pub fn set_buffer_size_power( &mut self,
+                                buffer_size_power: u32 ) -> Result<(),Error> {
+    let buffer_size = power_to_size(buffer_size_power);
+    if buffer_size < MIN_BUFFER_SIZE || buffer_size > MAX_BUFFER_SIZE {
+      return Err(invalid("buffer_size", buffer_size as f32));
+    }
+    
+    if buffer_size == self.buffer_size() {
+      return Ok(());
+    }
+    
+    let mut queue = mem::replace(&mut self.queue, Ring::new(0, Complex::zero()));
+    queue.resize(buffer_size, Complex::zero());
+    let mut right_queue = self.right.as_mut().map(|right| {
+      mem::replace(&mut right.queue, Ring::new(0, Complex::zero()))
+    });
+    if let Some(queue) = right_queue.as_mut() {
+      queue.resize(buffer_size, Complex::zero());
+    }
+    
+    self.reallocate(buffer_size, self.zero_pad_factor, self.decimation);
+    
+    self.queue = queue;
+    if let (Some(right), Some(queue)) = (self.right.as_mut(), right_queue) {
+      right.queue = queue;
+    }
+    Ok(())
+  }
+
+  fn update_window(&mut self) {
+    self.window.fill(&mut self.coefficients);
+    if let Some(small) = self.small.as_mut() {
+      self.window.fill(&mut small.coefficients);
+    }
+  }
+  
+  // Buffers can be of any size. The hop position, the filters and the
+  // queues carry over from one buffer to the next, so that a stream gives
+  // the same frames however it is split, dropped buffers aside. Only the
+  // silence skip counts whole buffers.
+  pub fn process(&mut self, buffer: Buffer) -> Result<ProcessSummary,Error> {
+    self.check_channels(buffer.data.channels())?;
+    
+    self.timed(|s| {
+      s.update_sample_rate(buffer.sample_rate);
+      s.update_silence(|| buffer.data.peak());
+      
+      match s.channel.take() {
+        None => {
+          for sample in buffer.data.iter_mut() {
+            let left = *sample.left;
+            let right = sample.right.as_ref().map_or(left, |r| **r);
+            s.push(left, right, None);
+          }
+        },
+        Some(channel) => {
+          let data = &*buffer.data;
+          let channels = data.channels();
+          for i in 0 .. data.len() {
+            let sample = |c: usize| data.channel(c).map_or(0.0, |x| x[i]);
+            let left = sample(0);
+            let right = if channels > 1 { sample(1) } else { left };
+            s.push(left, right, Some(channel.select(channels, sample)));
+          }
+          s.channel = Some(channel);
+        },
+      }
+      
+      if s.output_mode == OutputMode::Mute {
+        buffer.data.silence();
+      }
+      
+      Ok(())
+    })
+  }
+
+  // Pops and analyzes the mono samples available in `queue`, as the
+  // consumer. Only allocates when more samples than ever are available.
+  pub fn drain( &mut self,
+                queue: &SampleQueue,
+                sample_rate: f32 ) -> Result<ProcessSummary,Error> {
+    let mut drained = mem::replace(&mut self.drained, Vec::new());
+    drained.resize(queue.len(), 0.0);
+    let len = queue.pop_into(&mut drained);
+    
+    let result = self.process_planar(&drained[.. len], None, sample_rate);
+    self.drained = drained;
+    result
+  }
+
+  // Analyzes planar channels without touching them, mono input leaves
+  // `right` to `None`.
+  pub fn process_planar( &mut self,
+                         left: &[f32],
+                         right: Option<&[f32]>,
+                         sample_rate: f32 ) -> Result<ProcessSummary,Error> {
+    self.check_channels(if right.is_some() { 2 } else { 1 })?;
+    
+    let right = right.unwrap_or(left);
+    if right.len() != left.len() {
+      return Err(Error::ChannelLengthMismatch {
+        left: left.len(),
+        right: right.len(),
+      });
+    }
+    
+    self.timed(|s| {
+      s.update_sample_rate(sample_rate);
+      s.update_silence(|| {
+        left.iter().chain(right).fold(0.0, |peak, x| peak.max(x.abs()))
+      });
+      
+      let channel = s.channel.take();
+      for (l,r) in left.iter().zip(right.iter()) {
+        let selected = channel.as_ref().map(|channel| {
+          channel.select(2, |c| if c == 0 { *l } else { *r })
+        });
+        s.push(*l, *r, selected);
+      }
+      s.channel = channel;
+      
+      Ok(())
+    })
+  }
+
+  // Interleaved frames of `channels` samples, only the first two channels
+  // are analyzed unless some are selected.
+  pub fn process_interleaved( &mut self,
+                              data: &[f32],
+                              channels: usize,
+                              sample_rate: f32
+                            ) -> Result<ProcessSummary,Error> {
+    self.process_frames(data, channels, sample_rate, |x| x)
+  }
+
+  // Same as `process_interleaved` for 16-bit PCM.
+  pub fn process_i16( &mut self,
+                      data: &[i16],
+                      channels: usize,
+                      sample_rate: f32 ) -> Result<ProcessSummary,Error> {
+    self.process_frames(data, channels, sample_rate, |x| x as f32 / 32768.0)
+  }
+
+  fn process_frames<T: Copy>( &mut self,
+                              data: &[T],
+                              channels: usize,
+                              sample_rate: f32,
+                              convert: impl Fn(T) -> f32
+                            ) -> Result<ProcessSummary,Error> {
+    if channels == 0 {
+      return Err(invalid("channels", 0.0));
+    }
+    
+    if data.len() % channels != 0 {
+      return Err(Error::PartialFrame {
+        channels: channels,
+        remainder: data.len() % channels,
+      });
+    }
+    
+    self.check_channels(channels)?;
+    
+    self.timed(|s| {
+      s.update_sample_rate(sample_rate);
+      s.update_silence(|| {
+        data.chunks_exact(channels)
+          .flat_map(|frame| frame.iter().take(2))
+          .fold(0.0, |peak, x| peak.max(convert(*x).abs()))
+      });
+      
+      let channel = s.channel.take();
+      for frame in data.chunks_exact(channels) {
+        let left = convert(frame[0]);
+        let right = frame.get(1).map_or(left, |r| convert(*r));
+        let selected = channel.as_ref().map(|channel| {
+          channel.select(channels, |c| convert(frame[c]))
+        });
+        s.push(left, right, selected);
+      }
+      s.channel = channel;
+      
+      Ok(())
+    })
+  }
+
+  // Runs `f`, averaging its duration if there is a clock, and sums up
+  // what it pushed.
+  fn timed( &mut self,
+            f: impl FnOnce(&mut Spectrogram) -> Result<(),Error>
+          ) -> Result<ProcessSummary,Error> {
+    self.summary = ProcessSummary::default();
+    let start = self.clock.as_ref().map(|clock| clock());
+    let result = f(self);
+    
+    if let (Some(start), Some(clock)) = (start, self.clock.as_ref()) {
+      let us = (clock() - start) as f32;
+      self.stats.process_us = Some( match self.stats.process_us {
+        Some(mean) => mean + PROCESS_TIME_WEIGHT * (us - mean),
+        None => us,
+      });
+    }
+    
+    result.map(|_| self.summary)
+  }
+
+  // Input sample rates before and after the last change, if any since
+  // the last call. Buffered samples are dropped on a change.
+  pub fn take_sample_rate_change(&mut self) -> Option<(f32,f32)> {
+    self.sample_rate_change.take()
+  }
+
+  // Frequency range as set, if any since the last call, before it was
+  // clamped to the Nyquist frequency of a new sample rate.
+  pub fn take_clamped_range(&mut self) -> Option<(f32,f32)> {
+    self.clamped_range.take()
+  }
+
+  // Forgets everything heard so far, as when switching sources, leaving
+  // the configuration and the sample rate as is.
+  pub fn reset(&mut self) {
+    self.clear_buffers();
+    self.sample_rate_change = None;
+    self.left_meter.reset();
+    self.right_meter.reset();
+    self.gain_reference = MIN_GAIN_REFERENCE;
+    self.gain_updated_at = 0;
+    self.sample_count = 0;
+    self.silent_buffers = 0;
+    self.frame_count = 0;
+    self.frame_silent = false;
+    if let Some(history) = self.history.as_mut() {
+      history.frames.clear();
+    }
+  }
+
+  // Same as `reset_accumulator`.
+  pub fn reset_display(&mut self) {
+    self.clear_accumulator();
+  }
+
+  // Drops the buffered samples and the state of the filters.
+  fn clear_buffers(&mut self) {
+    self.decimator.reset();
+    if let Some(h) = self.high_pass.as_mut() {
+      h.reset();
+    }
+    if let Some(p) = self.pre_emphasis.as_mut() {
+      p.reset();
+    }
+    self.queue.fill(Complex::zero());
+    for x in self.output.iter_mut() {
+      *x = Complex::zero();
+    }
+    
+    if let Some(right) = self.right.as_mut() {
+      right.decimator.reset();
+      if let Some(h) = right.high_pass.as_mut() {
+        h.reset();
+      }
+      if let Some(p) = right.pre_emphasis.as_mut() {
+        p.reset();
+      }
+      right.queue.fill(Complex::zero());
+      for x in right.output.iter_mut() {
+        *x = Complex::zero();
+      }
+    }
+    
+    self.hop_pos = 0;
+    if let Some(small) = self.small.as_mut() {
+      small.hop_pos = 0;
+    }
+    self.since_frame = 0;
+    if let Some(r) = self.refine.as_mut() {
+      r.reset();
+    }
+    if let Some(r) = self.right.as_mut().and_then(|r| r.refine.as_mut()) {
+      r.reset();
+    }
+    self.onsets.reset();
+    self.smoothed_at = None;
+    if let Some(noise) = self.noise.as_mut() {
+      noise.left.reset();
+      noise.right.reset();
+      noise.small.reset();
+      noise.updated_at = None;
+    }
+    self.clear_accumulator();
+  }
+
+  fn update_sample_rate(&mut self, sample_rate: f32) {
+    let old = self.sample_rate;
+    let factor = self.decimation.factor() as f32;
+    let new = sample_rate / factor;
+    
+    if new == old {
+      return;
+    }
+    
+    // The placeholder rate is 1 until the first buffer.
+    if old != 1.0 {
+      self.sample_rate_change = Some((factor * old, sample_rate));
+      self.clear_buffers();
+    }
+    
+    self.sample_rate = new;
+    self.clamp_to_nyquist();
+    self.update_weights();
+    self.left_meter.set_sample_rate(sample_rate);
+    self.right_meter.set_sample_rate(sample_rate);
+    if let Some(h) = self.high_pass.as_mut() {
+      h.set_sample_rate(sample_rate);
+    }
+    if let Some(h) = self.right.as_mut().and_then(|r| r.high_pass.as_mut()) {
+      h.set_sample_rate(sample_rate);
+    }
+  }
+
+  // A range set before the first buffer, or kept across a drop of the
+  // sample rate, can end above the Nyquist frequency. It ends there
+  // instead, starting an octave below at most.
+  fn clamp_to_nyquist(&mut self) {
+    if check_nyquist(self.scale, self.to, self.sample_rate).is_ok() {
+      return;
+    }
+    
+    let nyquist = self.sample_rate / 2.0;
+    self.clamped_range = Some((self.from, self.to));
+    self.from = self.from.min(nyquist / 2.0);
+    self.to = nyquist;
+  }
+
+  // Counts the consecutive silent buffers, `peak` giving the largest
+  // absolute sample of the incoming one.
+  fn update_silence(&mut self, peak: impl FnOnce() -> f32) {
+    let threshold_db = match self.silence_skip {
+      Some(skip) => skip.threshold_db,
+      None => return,
+    };
+    
+    if peak() < 10.0_f32.powf(threshold_db / 20.0) {
+      self.silent_buffers = self.silent_buffers.saturating_add(1);
+    } else {
+      self.silent_buffers = 0;
+    }
+  }
+
+  fn check_channels(&self, channels: usize) -> Result<(),Error> {
+    match self.channel.as_ref().map(|channel| channel.required()) {
+      Some(required) if required > channels => Err(Error::TooFewChannels {
+        required: required,
+        actual: channels,
+      }),
+      _ => Ok(()),
+    }
+  }
+
+  fn skipping_silence(&self) -> bool {
+    self.silence_skip.map_or(false, |skip| self.silent_buffers >= skip.buffers)
+  }
+
+  // `selected` is the value of the selected channels, if any.
+  fn push(&mut self, left: f32, right: f32, selected: Option<f32>) {
+    // A single NaN would stay in the filters and the sums forever.
+    let finite = |x: f32| if x.is_finite() { x } else { 0.0 };
+    let selected_finite = selected.map_or(true, |x| x.is_finite());
+    if !left.is_finite() || !right.is_finite() || !selected_finite {
+      self.summary.non_finite += 1;
+      self.stats.non_finite = self.stats.non_finite.wrapping_add(1);
+    }
+    let left = finite(left);
+    let right = finite(right);
+    let selected = selected.map(finite);
+    
+    self.left_meter.push(left);
+    self.right_meter.push(right);
+    
+    let peak = left.abs().max(right.abs()).max(selected.map_or(0.0, f32::abs));
+    self.summary.samples += 1;
+    self.summary.peak = self.summary.peak.max(peak);
+    if peak >= 1.0 {
+      self.summary.clipped += 1;
+    }
+    
+    let hard_clip = self.hard_clip;
+    let clip = |x: f32| if hard_clip { x.max(-1.0).min(1.0) } else { x };
+    let left = clip(left);
+    let right = clip(right);
+    
+    let value = match selected {
+      Some(selected) => clip(selected),
+      None => match self.channel_mode {
+        _ if self.right.is_some() => left,
+        ChannelMode::Left  => left,
+        ChannelMode::Right => right,
+        ChannelMode::Mix | ChannelMode::Mid => (left + right) / 2.0,
+        ChannelMode::Side  => (left - right) / 2.0,
+      },
+    };
+    
+    let value = condition( value,
+                           self.high_pass.as_mut(),
+                           self.pre_emphasis.as_mut(),
+                           &mut self.decimator );
+    if let Some(value) = value {
+      self.queue.enqueue(Complex::new(value, 0.0));
+      self.since_frame += 1;
+    }
+    
+    if let Some(r) = self.right.as_mut() {
+      let value = condition( right,
+                             r.high_pass.as_mut(),
+                             r.pre_emphasis.as_mut(),
+                             &mut r.decimator );
+      if let Some(value) = value {
+        r.queue.enqueue(Complex::new(value, 0.0));
+      }
+    }
+
+    self.sample_count += 1;
+    self.stats.samples = self.stats.samples.wrapping_add(1);
+    self.hop_pos += 1;
+    if self.hop_pos >= self.effective_hop() {
+      self.hop_pos = 0;
+      if !self.skipping_silence() && self.quality != Quality::Minimal {
+        self.analyze();
+      }
+    }
+    
+    if self.skipping_silence() || self.quality == Quality::Minimal {
+      return;
+    }
+    
+    let factor = self.effective_hop() / self.hop;
+    if let Some(small) = self.small.as_mut() {
+      small.hop_pos += 1;
+      if small.hop_pos >= small.config.hop * factor {
+        small.hop_pos = 0;
+        self.analyze_small();
+      }
+    }
+  }
+  
+  fn analyze(&mut self) {
+    let hop = self.since_frame;
+    self.since_frame = 0;
+    
+    // The first FFT waits for a full buffer of input rather than the
+    // zeros the queue starts with.
+    if !self.queue.is_full() {
+      return;
+    }
+
+    let goertzel_bins = self.goertzel_bins();
+    transform( &self.queue,
+               &*self.fft,
+               &self.coefficients,
+               goertzel_bins.clone(),
+               &mut self.input,
+               &mut self.output );
+
+    let decay = self.averaging_decay(self.effective_hop()).map(|a| a as f64);
+    let peak_decay = self.peak_decay(self.effective_hop());
+    let scale = 2.0 / self.coefficients.len() as f32;
+    
+    for (i,bin) in self.output[1 .. self.output.len() / 2].iter().enumerate() {
+      self.onsets.push_bin(i, scale * bin.norm());
+    }
+    
+    let accumulation = Accumulation {
+      hop: hop,
+      scale: scale,
+      weights: &self.weights,
+      mode: self.accumulate,
+      decay: decay,
+      peak_decay: peak_decay,
+    };
+    accumulate_frame( &self.output,
+                      self.refine.as_mut(),
+                      &accumulation,
+                      &mut self.freq_sum,
+                      &mut self.peaks );
+    
+    self.freq_n += 1;
+    self.stats.ffts = self.stats.ffts.wrapping_add(1);
+    self.summary.fft_frames += 1;
+
+    // Frames overlapping the one an onset was found in see the same onset.
+    let factor = self.decimation.factor();
+    let min_gap = (self.coefficients.len() * factor) as u64;
+    let rate = self.sample_rate * factor as f32;
+    let hop_ms = 1000.0 * self.effective_hop() as f32 / rate;
+    let onset_decay = (-hop_ms / ONSET_WINDOW_MS).exp();
+    self.onsets.end_frame(self.sample_count, min_gap, onset_decay);
+    
+    if let Some(right) = self.right.as_mut() {
+      transform( &right.queue,
+                 &*self.fft,
+                 &self.coefficients,
+                 goertzel_bins,
+                 &mut self.input,
+                 &mut right.output );
+      
+      accumulate_frame( &right.output,
+                        right.refine.as_mut(),
+                        &accumulation,
+                        &mut right.freq_sum,
+                        &mut right.peaks );
+    }
+    
+    // Taken out so that the callback can see the frame.
+    if let Some(mut callback) = self.on_frame.take() {
+      callback.count += 1;
+      if callback.count >= callback.every {
+        callback.count = 0;
+        (callback.f)(&self.frame_data());
+      }
+      self.on_frame = Some(callback);
+    }
+  }
+  
+  fn analyze_small(&mut self) {
+    let decay = self.small.as_ref()
+      .and_then(|small| self.averaging_decay(small.config.hop))
+      .map(|a| a as f64);
+    
+    let small = match self.small.as_mut() {
+      Some(small) => small,
+      None => return,
+    };
+    
+    if !self.queue.is_full() {
+      return;
+    }
+    
+    transform( &self.queue,
+               &*small.fft,
+               &small.coefficients,
+               None,
+               &mut small.input,
+               &mut small.output );
+    
+    let accumulation = Accumulation {
+      hop: 0,
+      scale: 2.0 / small.coefficients.len() as f32,
+      weights: &small.weights,
+      mode: self.accumulate,
+      decay: decay,
+      peak_decay: 1.0,
+    };
+    accumulate_frame( &small.output,
+                      None,
+                      &accumulation,
+                      &mut small.freq_sum,
+                      &mut [] );
+    
+    small.freq_n += 1;
+  }
+  
+  // Complex bins of the newest FFT, from the first one above DC to the
+  // last one below the Nyquist frequency, of the left channel when split.
+  // Bin `i` is centered at `(i + 1) * sample_rate / fft_size` with the
+  // decimated sample rate, `bin_to_hz(i)` without refinement. Each FFT
+  // overwrites them, so after a `process` they are those of its last FFT
+  // if it computed any, of an earlier one otherwise, and zero since the
+  // last `reset` or change of the sample rate or FFT size until one is
+  // computed. The Goertzel backend leaves the bins outside the frequency
+  // range at zero.
+  pub fn last_spectrum(&self) -> &[Complex<f32>] {
+    &self.output[1 .. self.output.len() / 2]
+  }
+
+  // Same as `last_spectrum` for the right channel, `None` unless split.
+  pub fn last_spectrum_right(&self) -> Option<&[Complex<f32>]> {
+    self.right.as_ref().map(|right| &right.output[1 .. right.output.len() / 2])
+  }
+
+  // Magnitudes accumulated since the last `draw_frame`, `None` if no FFT
+  // has been computed in the meantime.
+  pub fn frame(&self) -> Option<Frame> {
+    if self.freq_n == 0 {
+      return None;
+    }
+
+    Some( Frame {
+      sum: &self.freq_sum,
+      n: match self.accumulate {
+        Accumulate::Average if self.averaging_window_ms.is_none() => self.freq_n,
+        _ => 1,
+      },
+      fft_len: self.frame_fft_len(),
+      sample_rate: self.sample_rate,
+      floor: &[],
+      strength: 0.0,
+    }.minus_noise(self.noise.as_ref(), |noise| &noise.left))
+  }
+
+  // Replaces the accumulated frame with `magnitudes`, one per bin of
+  // `frame`, to draw known frames. The smaller FFT of the dual
+  // resolution is left out, and the smoothed spectrum follows the frame.
+  #[cfg(any(test, feature = "test-util"))]
+  pub fn set_frame_for_test(&mut self, magnitudes: &[f32]) -> Result<(),Error> {
+    if magnitudes.len() != self.freq_sum.len() {
+      return Err(invalid("magnitudes", magnitudes.len() as f32));
+    }
+    
+    for (sum,&m) in self.freq_sum.iter_mut().zip(magnitudes) {
+      *sum = m as f64;
+    }
+    self.freq_n = 1;
+    
+    if self.is_smoothed() {
+      self.smoothed.copy_from_slice(&self.freq_sum);
+    }
+    if let Some(small) = self.small.as_mut() {
+      small.freq_n = 0;
+    }
+    Ok(())
+  }
+
+  // The frame drawn, smoothed once there is a smoothed spectrum.
+  fn display_frame(&self) -> Option<Frame> {
+    if !self.is_smoothed() {
+      return self.frame();
+    }
+    
+    Some( Frame {
+      sum: &self.smoothed,
+      n: 1,
+      fft_len: self.frame_fft_len(),
+      sample_rate: self.sample_rate,
+      floor: &[],
+      strength: 0.0,
+    }.minus_noise(self.noise.as_ref(), |noise| &noise.left))
+  }
+
+  // Same as `display_frame` for the right channel of the split-screen
+  // mode.
+  fn right_frame(&self) -> Option<Frame> {
+    let right = self.right.as_ref()?;
+    let frame = self.display_frame()?;
+    let sum = if self.is_smoothed() { &right.smoothed } else { &right.freq_sum };
+    let frame = Frame { sum: sum, ..frame };
+    Some(frame.minus_noise(self.noise.as_ref(), |noise| &noise.right))
+  }
+
+  fn is_smoothed(&self) -> bool {
+    self.smoothing.is_some() && self.smoothed_at.is_some()
+  }
+
+  // Peaks of the peak layer, `None` without one.
+  fn peak_frame(&self) -> Option<Frame> {
+    if self.peaks.is_empty() {
+      return None;
+    }
+    
+    Some( Frame {
+      sum: &self.peaks,
+      n: 1,
+      fft_len: self.frame_fft_len(),
+      sample_rate: self.sample_rate,
+      floor: &[],
+      strength: 0.0,
+    })
+  }
+
+  fn right_peak_frame(&self) -> Option<Frame> {
+    let right = self.right.as_ref()?;
+    let frame = self.peak_frame()?;
+    Some(Frame { sum: &right.peaks, ..frame })
+  }
+
+  // Same as `frame` for the smaller FFT of the dual resolution.
+  fn small_frame(&self) -> Option<Frame> {
+    let small = self.small.as_ref()?;
+    if small.freq_n == 0 {
+      return None;
+    }
+    
+    Some( Frame {
+      sum: &small.freq_sum,
+      n: match self.accumulate {
+        Accumulate::Average if self.averaging_window_ms.is_none() => small.freq_n,
+        _ => 1,
+      },
+      fft_len: small.output.len(),
+      sample_rate: self.sample_rate,
+      floor: &[],
+      strength: 0.0,
+    }.minus_noise(self.noise.as_ref(), |noise| &noise.small))
+  }
+  
+  // Strongest bin within the frequency range, refined by fitting a
+  // parabola through its neighbors.
+  pub fn peak(&self) -> Option<Peak> {
+    let frame = self.frame()?;
+    
+    let from_hz = self.frequency_at(0.0);
+    let to_hz = self.frequency_at(1.0);
+    
+    let (bin,magnitude) = (0 .. frame.len())
+      .filter(|&bin| {
+        let hz = frame.frequency(bin);
+        hz >= from_hz && hz <= to_hz
+      })
+      .map(|bin| (bin, frame.magnitude(bin)))
+      .max_by(|(_,a),(_,b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))?;
+    
+    if magnitude <= self.peak_threshold {
+      return None;
+    }
+    
+    let (hz,magnitude) = frame.refine(bin);
+    let key = self.hz_to_key(hz);
+    let nearest = key.round();
+    
+    Some( Peak {
+      frequency: hz,
+      magnitude: magnitude,
+      key: nearest as i32,
+      cents: 100.0 * (key - nearest),
+    })
+  }
+  
+  // Fundamental within the frequency range maximizing the harmonic
+  // product spectrum, which still finds a missing fundamental from its
+  // harmonics. `None` below the confidence threshold.
+  pub fn pitch(&self) -> Option<PitchEstimate> {
+    let frame = self.frame()?;
+    
+    let max = (0 .. frame.len()).map(|bin| frame.magnitude(bin))
+      .fold(0.0, f32::max);
+    if !(max > 0.0) {
+      return None;
+    }
+    
+    // Strongest bin around harmonic `h` of the fundamental at `bin`, the
+    // uncertainty growing with `h`.
+    let harmonic = |bin: usize, h: usize| {
+      let center = h * (bin + 1) - 1;
+      if center >= frame.len() {
+        return None;
+      }
+      let from = center.saturating_sub(h / 2);
+      let to = (center + h / 2).min(frame.len() - 1);
+      (from ..= to)
+        .map(|bin| (bin, frame.magnitude(bin)))
+        .max_by(|(_,a),(_,b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+    };
+    
+    // Mean log magnitude of the harmonics relative to the frame maximum,
+    // missing harmonics counting as a floor rather than zero.
+    let score = |bin: usize| {
+      let (n,sum) = (1 ..= HPS_HARMONICS)
+        .filter_map(|h| harmonic(bin, h))
+        .fold((0, 0.0), |(n,sum), (_,m)| {
+          (n + 1, sum + (m.max(HPS_FLOOR * max) / max).ln())
+        });
+      sum / n as f32
+    };
+    
+    let from_hz = self.frequency_at(0.0);
+    let to_hz = self.frequency_at(1.0);
+    
+    let (bin,score) = (0 .. frame.len())
+      .filter(|&bin| {
+        let hz = frame.frequency(bin);
+        hz >= from_hz && hz <= to_hz
+      })
+      .map(|bin| (bin, score(bin)))
+      .max_by(|(_,a),(_,b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))?;
+    
+    let confidence = score.exp();
+    if confidence < self.pitch_threshold {
+      return None;
+    }
+    
+    // The strongest harmonic gives the most precise frequency.
+    let (h,(strongest,_)) = (1 ..= HPS_HARMONICS)
+      .filter_map(|h| harmonic(bin, h).map(|b| (h, b)))
+      .max_by(|(_,(_,a)),(_,(_,b))| {
+        a.partial_cmp(b).unwrap_or(Ordering::Equal)
+      })?;
+    
+    let hz = frame.refine(strongest).0 / h as f32;
+    let key = self.hz_to_key(hz);
+    let nearest = key.round();
+    
+    Some( PitchEstimate {
+      frequency: hz,
+      key: nearest as i32,
+      cents: 100.0 * (key - nearest),
+      confidence: confidence,
+    })
+  }
+
+  pub fn pitch_threshold(&self) -> f32 {
+    self.pitch_threshold
+  }
+
+  // Confidence from 0 to 1 below which `pitch` finds nothing.
+  pub fn set_pitch_threshold(&mut self, threshold: f32) -> Result<(),Error> {
+    if !(threshold >= 0.0 && threshold <= 1.0) {
+      return Err(invalid("pitch_threshold", threshold));
+    }
+    
+    self.pitch_threshold = threshold;
+    Ok(())
+  }
+  
+  // Features of the bins within the frequency range, `None` for silence.
+  pub fn features(&self) -> Option<Features> {
+    let frame = self.frame()?;
+    
+    let from_hz = self.frequency_at(0.0);
+    let to_hz = self.frequency_at(1.0);
+    let bins = || frame.iter().filter(|&(hz,_)| hz >= from_hz && hz <= to_hz);
+    
+    let (n,sum,weighted,log_power,power) = bins().fold(
+      (0, 0.0, 0.0, 0.0, 0.0),
+      |(n,sum,weighted,log_power,power), (hz,m)| {
+        let p = (m * m).max(std::f32::MIN_POSITIVE);
+        (n + 1, sum + m, weighted + hz * m, log_power + p.ln(), power + p)
+      });
+    
+    if !(sum > 0.0) {
+      return None;
+    }
+    
+    let mut below = 0.0;
+    let rolloff = bins()
+      .find(|&(_,m)| {
+        below += m;
+        below >= 0.85 * sum
+      })
+      .map_or(to_hz, |(hz,_)| hz);
+    
+    Some( Features {
+      centroid: weighted / sum,
+      rolloff: rolloff,
+      flatness: (log_power / n as f32).exp() / (power / n as f32),
+    })
+  }
+  
+  // Bins of the current frame within the frequency range, without
+  // resetting the accumulator. No bins if there is no frame.
+  pub fn frame_data(&self) -> FrameData {
+    let from_hz = self.frequency_at(0.0);
+    let to_hz = self.frequency_at(1.0);
+    
+    let in_range = |&bin: &usize| {
+      let hz = self.bin_to_hz(bin);
+      hz >= from_hz && hz <= to_hz
+    };
+    
+    let (bins,noise_floor) = match self.frame() {
+      Some(frame) => {
+        let bins = (0 .. frame.len()).filter(in_range)
+          .map(|bin| Bin { hz: frame.frequency(bin), magnitude: frame.magnitude(bin) })
+          .collect();
+        let noise_floor = self.noise.as_ref().map(|_| {
+          (0 .. frame.len()).filter(in_range)
+            .map(|bin| frame.noise_floor(bin))
+            .collect()
+        });
+        (bins, noise_floor)
+      },
+      None => (Vec::new(), None),
+    };
+    
+    FrameData {
+      sample_rate: self.sample_rate,
+      fft_size: self.output.len(),
+      decimation: self.decimation.factor(),
+      bins: bins,
+      noise_floor: noise_floor,
+    }
+  }
+
+  // Same as `frame_data`, as JSON.
+  pub fn frame_to_json(&self) -> String {
+    self.frame_data().to_json()
+  }
+
+  // Calls `f` from `process` with the frame so far every `every_n_ffts`
+  // FFTs, replacing any previous callback. Copying the frame allocates.
+  pub fn on_frame( &mut self,
+                   every_n_ffts: usize,
+                   f: impl FnMut(&FrameData) + Send + 'static
+                 ) -> Result<(),Error> {
+    if every_n_ffts == 0 {
+      return Err(invalid("every_n_ffts", 0.0));
+    }
+    
+    self.on_frame = Some( FrameCallback {
+      every: every_n_ffts,
+      count: 0,
+      f: Box::new(f),
+    });
+    Ok(())
+  }
+
+  pub fn clear_on_frame(&mut self) {
+    self.on_frame = None;
+  }
+  
+  pub fn draw_frame(&mut self, line: Line) -> Result<(),Error> {
+    self.update_gain();
+    self.update_smoothing();
+    self.update_noise_floor();
+    self.draw(line, false, None)?;
+    self.capture_history();
+    self.end_frame();
+    Ok(())
+  }
+
+  // Same as `draw_frame` without resetting the accumulator.
+  pub fn draw_frame_peek(&self, line: Line) -> Result<(),Error> {
+    self.draw(line, false, None)
+  }
+
+  // Same as `draw_frame` for a vertical line, `flip` puts the low
+  // frequencies at the bottom.
+  pub fn draw_column(&mut self, column: Line, flip: bool) -> Result<(),Error> {
+    self.update_gain();
+    self.update_smoothing();
+    self.update_noise_floor();
+    self.draw(column, flip, None)?;
+    self.capture_history();
+    self.end_frame();
+    Ok(())
+  }
+
+  // Same as `draw_frame`, marking the pixels where the peak layer is at
+  // a higher display level than the frame with `peak_color`, as an
+  // outline of the peaks over the spectrum. Draws only the frame without
+  // a peak layer.
+  pub fn draw_frame_layers( &mut self,
+                            line: Line,
+                            peak_color: [u8;3] ) -> Result<(),Error> {
+    self.update_gain();
+    self.update_smoothing();
+    self.update_noise_floor();
+    self.draw(line, false, Some(peak_color))?;
+    self.capture_history();
+    self.end_frame();
+    Ok(())
+  }
+
+  // Same as `draw_frame` as `bars` bars evenly spread over the frequency
+  // range, of the left channel when split, with `gap_px` background
+  // pixels between them. Each bar is the maximum of the bins it spans,
+  // or interpolated between the bins around its center if there are
+  // none. The extra pixels go to the first bars.
+  pub fn draw_bars( &mut self,
+                    line: Line,
+                    bars: usize,
+                    gap_px: usize ) -> Result<(),Error> {
+    let gaps = gap_px.saturating_mul(bars.saturating_sub(1));
+    if bars == 0 || line.len < gaps.saturating_add(bars) {
+      return Err(invalid("bars", bars as f32));
+    }
+    
+    self.update_gain();
+    self.update_smoothing();
+    self.update_noise_floor();
+    if self.skipping_silence() {
+      self.draw_background(line);
+    } else if self.quality == Quality::Minimal {
+      self.draw_level_bar(line, false);
+    } else if let Some(frame) = self.display_frame() {
+      self.draw_bars_of(frame, line, bars, gap_px);
+    }
+    self.capture_history();
+    self.end_frame();
+    Ok(())
+  }
+
+  fn draw_bars_of(&self, frame: Frame, line: Line, bars: usize, gap_px: usize) {
+    let mut render = self.render(frame, bars);
+    render.dx = 0.5 / bars as f32;
+    
+    let background = self.color_map.color(self.adjust(0.0));
+    let background_alpha = match self.alpha {
+      Alpha::Opaque => u8::max_value(),
+      Alpha::Intensity => 0,
+    };
+    
+    let gaps = gap_px * (bars - 1);
+    let width = line.len - gaps;
+    let start = |bar: usize| bar * (width / bars) + bar.min(width % bars) + bar * gap_px;
+    let mut cached = None;
+    
+    for mut pixel in line.data.iter_mut() {
+      // The bar starting at or before the pixel, if it isn't in the gap.
+      let mut bar = (pixel.x * bars / line.len).min(bars - 1);
+      while bar > 0 && start(bar) > pixel.x {
+        bar -= 1;
+      }
+      while bar + 1 < bars && start(bar + 1) <= pixel.x {
+        bar += 1;
+      }
+      if pixel.x >= start(bar + 1) - gap_px {
+        pixel.set(background, background_alpha);
+        continue;
+      }
+      
+      let v = match cached {
+        Some((b,v)) if b == bar => v,
+        _ => {
+          let x = (bar as f32 + 0.5) / bars as f32;
+          let m = self.pixel_magnitude(&render, x, render.dx, Reduce::Max);
+          let v = self.displayed(&render, m);
+          cached = Some((bar, v));
+          v
+        },
+      };
+      
+      let shade = self.adjust(v) + self.dither_at(pixel.x);
+      let alpha = match self.alpha {
+        Alpha::Opaque => u8::max_value(),
+        Alpha::Intensity => to_byte(v),
+      };
+      pixel.set(self.color_map.color(shade), alpha);
+    }
+  }
+
+  pub fn reset_accumulator(&mut self) {
+    self.clear_accumulator();
+  }
+
+  // Draws into a row laid out like `ImageData.data`.
+  pub fn draw_into_rgba( &mut self,
+                         buf: &mut [u8],
+                         width: usize ) -> Result<(),Error> {
+    self.draw_into(buf, width, PixelFormat::Rgba8)
+  }
+
+  // Draws a row of `width` pixels packed as `format`.
+  pub fn draw_into( &mut self,
+                    buf: &mut [u8],
+                    width: usize,
+                    format: PixelFormat ) -> Result<(),Error> {
+    let expected = format.bytes() * width;
+    if buf.len() != expected {
+      return Err(Error::BufferSize { expected: expected, actual: buf.len() });
+    }
+    
+    self.draw_frame( Line {
+      len: width,
+      data: &mut LineData::new(buf, format),
+    })
+  }
+
+  // Draws `out.len() / 4` pixels as tightly packed RGBA bytes, ready to
+  // be uploaded as a texture row. The lowest frequency is at x = 0, or at
+  // both edges when the stereo is split.
+  pub fn draw_row_rgba(&mut self, out: &mut [u8]) -> Result<(),Error> {
+    let width = out.len() / 4;
+    self.draw_into_rgba(out, width)
+  }
+
+  fn draw( &self,
+           line: Line,
+           flip: bool,
+           peak_color: Option<[u8;3]> ) -> Result<(),Error> {
+    if self.skipping_silence() {
+      self.draw_background(line);
+      return Ok(());
+    }
+    
+    if self.quality == Quality::Minimal {
+      self.draw_level_bar(line, flip);
+      return Ok(());
+    }
+    
+    let frame = match self.display_frame() {
+      Some(frame) => frame,
+      None => return Ok(()),
+    };
+
+    if line.len == 0 {
+      return Ok(());
+    }
+    
+    // The left half gets the extra pixel of odd lengths.
+    let (left_len, mut right) = match self.right_frame() {
+      Some(right) => {
+        let left_len = (line.len + 1) / 2;
+        (left_len, Some(self.render(right, line.len - left_len)))
+      },
+      None => (line.len, None),
+    };
+    
+    let mut render = self.render(frame, left_len);
+    
+    // Both channels share the gain, so that their balance shows.
+    if let Some(right) = right.as_mut() {
+      let gain = render.gain.min(right.gain);
+      render.gain = gain;
+      right.gain = gain;
+    }
+    
+    let (left_peaks,right_peaks) = match peak_color {
+      Some(_) => (
+        self.peak_frame().map(|frame| self.render_peaks(frame, &render, left_len)),
+        right.as_ref().and_then(|right| {
+          let frame = self.right_peak_frame()?;
+          Some(self.render_peaks(frame, right, line.len - left_len))
+        }),
+      ),
+      None => (None, None),
+    };
+    
+    for mut pixel in line.data.iter_mut() {
+      let x = if flip { line.len - 1 - pixel.x } else { pixel.x };
+      let (render, peaks, x) = match &right {
+        Some(right) if x >= left_len =>
+          (right, &right_peaks, pixel_position(line.len - 1 - x, line.len - left_len)),
+        _ => (&render, &left_peaks, pixel_position(x, left_len)),
+      };
+      let v = self.supersampled_at(render, x);
+
+      let shade = self.adjust(v) + self.dither_at(pixel.x);
+      let [r,g,b] = self.color_map.color(shade);
+      let mut alpha = match self.alpha {
+        Alpha::Opaque => u8::max_value(),
+        Alpha::Intensity =>
+          to_byte(v),
+      };
+      
+      let marker = self.grid.and_then(|grid| self.marker_at(grid, x, render.dx));
+      let [r,g,b] = match marker {
+        Some([mr,mg,mb,ma]) => {
+          alpha = alpha.max(ma);
+          [blend(r, mr, ma), blend(g, mg, ma), blend(b, mb, ma)]
+        },
+        None => [r,g,b],
+      };
+      
+      let peak = peaks.as_ref().map_or(0.0, |peaks| self.supersampled_at(peaks, x));
+      let level = |v: f32| (v.max(0.0).min(1.0) * PEAK_LEVELS).floor();
+      let [r,g,b] = match peak_color {
+        Some(color) if level(peak) > level(v) => {
+          alpha = u8::max_value();
+          color
+        },
+        _ => [r,g,b],
+      };
+      
+      pixel.set([r,g,b], alpha);
+    }
+    
+    Ok(())
+  }
+
+  fn draw_background(&self, line: Line) {
+    let color = self.color_map.color(self.adjust(0.0));
+    let alpha = match self.alpha {
+      Alpha::Opaque => u8::max_value(),
+      Alpha::Intensity => 0,
+    };
+    
+    for mut pixel in line.data.iter_mut() {
+      pixel.set(color, alpha);
+    }
+  }
+
+  // The RMS level from `LEVEL_BAR_FLOOR_DB` to 0 dBFS as a bar from the
+  // low end of the line in the color of the level, over the background.
+  fn draw_level_bar(&self, line: Line, flip: bool) {
+    let level = (1.0 - self.levels().rms_db / LEVEL_BAR_FLOOR_DB).max(0.0).min(1.0);
+    let lit = (level * line.len as f32).round() as usize;
+    
+    let color = self.color_map.color(self.adjust(level));
+    let background = self.color_map.color(self.adjust(0.0));
+    let (alpha, background_alpha) = match self.alpha {
+      Alpha::Opaque => (u8::max_value(), u8::max_value()),
+      Alpha::Intensity => (to_byte(level), 0),
+    };
+    
+    for mut pixel in line.data.iter_mut() {
+      let x = if flip { line.len - 1 - pixel.x } else { pixel.x };
+      if x < lit {
+        pixel.set(color, alpha);
+      } else {
+        pixel.set(background, background_alpha);
+      }
+    }
+  }
+
+  // The peaks are drawn with the gain of the frame.
+  fn render_peaks<'a>( &'a self,
+                       frame: Frame<'a>,
+                       render: &Render,
+                       len: usize ) -> Render<'a> {
+    Render {
+      small: None,
+      gain: render.gain,
+      ..self.render(frame, len)
+    }
+  }
+
+  fn render<'a>(&'a self, frame: Frame<'a>, len: usize) -> Render<'a> {
+    let gate = self.noise_gate.map(|gate| gate.magnitude(&frame));
+    let gain = match self.auto_gain {
+      None => 1.0,
+      Some(AutoGain::Frame) => 1.0 / self.frame_max(&frame),
+      Some(AutoGain::Running {..}) | Some(AutoGain::Hold {..}) =>
+        1.0 / self.gain_reference,
+    };
+    
+    Render {
+      frame: frame,
+      // Split channels are both drawn at the main resolution.
+      small: if self.right.is_some() { None } else { self.small_frame() },
+      // Each pixel covers the frequencies half-way to its neighbors.
+      dx: 0.5 * pixel_width(len),
+      gate: gate,
+      gain: gain,
+    }
+  }
+
+  // Mean displayed value of `supersample` equal parts of the pixel
+  // centered on `x`.
+  fn supersampled_at(&self, render: &Render, x: f32) -> f32 {
+    if self.supersample == 1 {
+      return self.value_at(render, x, render.dx);
+    }
+    
+    let n = self.supersample as f32;
+    let dx = render.dx / n;
+    let sum: f32 = (0 .. self.supersample)
+      .map(|i| self.value_at(render, x - render.dx + (2 * i + 1) as f32 * dx, dx))
+      .sum();
+    sum / n
+  }
+
+  // Displayed value of a pixel spanning `x - dx` to `x + dx`.
+  fn value_at(&self, render: &Render, x: f32, dx: f32) -> f32 {
+    let m = self.pixel_magnitude(render, x, dx, self.reduce);
+    self.displayed(render, m)
+  }
+
+  // Applies the gate, the gain and the amplitude scale to a magnitude.
+  fn displayed(&self, render: &Render, m: f32) -> f32 {
+    let m = render.gate.map_or(m, |gate| apply_gate(m, gate));
+    self.amplitude_scale.apply(render.gain * m)
+  }
+
+  // Magnitude of a pixel spanning `x - dx` to `x + dx`, crossfading to
+  // the small FFT around the crossover key.
+  fn pixel_magnitude( &self,
+                      render: &Render,
+                      x: f32,
+                      dx: f32,
+                      reduce: Reduce ) -> f32 {
+    let m = self.magnitude_at(&render.frame, x, dx, reduce);
+    
+    if let (Some(small), Some(config)) = (&render.small, self.dual_resolution()) {
+      let key = to_piano_key(self.frequency_at(x), self.tuning_a4);
+      let t = ((key - config.crossover_key) / CROSSFADE_KEYS + 0.5)
+        .max(0.0).min(1.0);
+      if t > 0.0 {
+        return (1.0 - t) * m + t * self.magnitude_at(small, x, dx, reduce);
+      }
+    }
+    
+    m
+  }
+
+  // What `draw_frame` would draw at pixel `x` of a line of `line_len`
+  // pixels, without resetting the accumulator. `None` before the first
+  // FFT or past the end of the line.
+  pub fn value_at_pixel(&self, x: usize, line_len: usize) -> Option<Readout> {
+    if x >= line_len {
+      return None;
+    }
+    
+    let frame = self.display_frame()?;
+    
+    // Split the same way as `draw`.
+    let (left_len,right) = match self.right_frame() {
+      Some(right) => {
+        let left_len = (line_len + 1) / 2;
+        (left_len, Some(self.render(right, line_len - left_len)))
+      },
+      None => (line_len, None),
+    };
+    
+    let mut render = self.render(frame, left_len);
+    let (render,x) = match right {
+      Some(mut right) => {
+        let gain = render.gain.min(right.gain);
+        render.gain = gain;
+        right.gain = gain;
+        if x >= left_len {
+          (right, pixel_position(line_len - 1 - x, line_len - left_len))
+        } else {
+          (render, pixel_position(x, left_len))
+        }
+      },
+      None => (render, pixel_position(x, left_len)),
+    };
+    
+    let magnitude = self.pixel_magnitude(&render, x, render.dx, self.reduce);
+    Some(self.readout(x, magnitude, self.supersampled_at(&render, x)))
+  }
+
+  // Same as `value_at_pixel` at exactly `hz`, interpolated between the
+  // bins around it, of the left channel when split. `None` outside the
+  // frequency range.
+  pub fn value_at_hz(&self, hz: f32) -> Option<Readout> {
+    let x = self.position_at(hz);
+    if !(x >= 0.0 && x <= 1.0) {
+      return None;
+    }
+    
+    let render = self.render(self.display_frame()?, 1);
+    let magnitude = self.pixel_magnitude(&render, x, 0.0, self.reduce);
+    Some(self.readout(x, magnitude, self.value_at(&render, x, 0.0)))
+  }
+
+  fn readout(&self, x: f32, magnitude: f32, value: f32) -> Readout {
+    let hz = self.frequency_at(x);
+    Readout {
+      frequency: hz,
+      magnitude: magnitude,
+      db: 20.0 * magnitude.max(std::f32::MIN_POSITIVE).log10(),
+      value: value,
+      note: key_to_note_name(self.hz_to_key(hz)),
+    }
+  }
+
+  // Applies brightness, contrast and inversion to a displayed value.
+  fn adjust(&self, value: f32) -> f32 {
+    let value = (value - 0.5) * self.contrast + 0.5 + self.brightness;
+    let value = value.max(0.0).min(1.0);
+    if self.invert { 1.0 - value } else { value }
+  }
+
+  // Offset of less than one color step, zero on average.
+  fn dither_at(&self, x: usize) -> f32 {
+    if !self.dither {
+      return 0.0;
+    }
+    
+    let threshold = DITHER[self.frame_count % 4][x % 4] as f32;
+    ((threshold + 0.5) / 16.0 - 0.5) / u8::max_value() as f32
+  }
+
+  // Marker color of the pixel spanning `x - dx` to `x + dx` if it holds
+  // the center of an A or C key, A winning.
+  fn marker_at(&self, grid: Grid, x: f32, dx: f32) -> Option<[u8;4]> {
+    let from = to_piano_key(self.frequency_at(x - dx), self.tuning_a4);
+    let to = to_piano_key(self.frequency_at(x + dx), self.tuning_a4);
+    
+    // Whether a key `offset` modulo 12 lies in `from .. to`.
+    let holds = |offset: f32| {
+      let key = offset + 12.0 * ((from - offset) / 12.0).ceil();
+      key < to
+    };
+    
+    if holds(1.0) {
+      Some(grid.octave_color)
+    } else if holds(4.0) {
+      Some(grid.c_color)
+    } else {
+      None
+    }
+  }
+
+  // Magnitude of the bins covered by a pixel spanning `x - dx` to
+  // `x + dx`.
+  fn magnitude_at( &self,
+                   frame: &Frame,
+                   x: f32,
+                   dx: f32,
+                   reduce: Reduce ) -> f32 {
+    frame
+      .reduce( self.frequency_at(x - dx),
+               self.frequency_at(x + dx),
+               reduce )
+      .unwrap_or_else(|| frame.magnitude_at(self.frequency_at(x)))
+  }
+
+  // Largest magnitude within the frequency range.
+  fn frame_max(&self, frame: &Frame) -> f32 {
+    let from_hz = self.frequency_at(0.0);
+    let to_hz = self.frequency_at(1.0);
+    frame.reduce(from_hz, to_hz, Reduce::Max)
+      .unwrap_or_else(|| frame.magnitude_at(self.frequency_at(0.5)))
+      .max(MIN_GAIN_REFERENCE)
+  }
+
+  // Moves the running maximum towards the frame about to be drawn.
+  fn update_gain(&mut self) {
+    let (attack_s, release_s) = match self.auto_gain {
+      Some(AutoGain::Running { attack_s, release_s }) => (attack_s, release_s),
+      Some(AutoGain::Hold { hold_seconds }) => (0.0, hold_seconds),
+      _ => return,
+    };
+    
+    let max = match self.frame() {
+      Some(frame) => self.frame_max(&frame),
+      None => return,
+    };
+    
+    let rate = self.sample_rate * self.decimation.factor() as f32;
+    let elapsed = (self.sample_count - self.gain_updated_at) as f32 / rate;
+    self.gain_updated_at = self.sample_count;
+    
+    let time = if max > self.gain_reference { attack_s } else { release_s };
+    let a = if time > 0.0 { (-elapsed / time).exp() } else { 0.0 };
+    self.gain_reference = a * self.gain_reference + (1.0 - a) * max;
+  }
+
+  // Moves the smoothed spectrum towards the frame about to be drawn.
+  fn update_smoothing(&mut self) {
+    let smoothing = match self.smoothing {
+      Some(smoothing) => smoothing,
+      None => return,
+    };
+    
+    let n = match self.frame() {
+      Some(frame) => frame.n,
+      None => return,
+    };
+    
+    // The first frame is taken as is.
+    let rate = self.sample_rate * self.decimation.factor() as f32;
+    let elapsed_ms = match self.smoothed_at {
+      Some(at) => 1000.0 * (self.sample_count - at) as f32 / rate,
+      None => std::f32::INFINITY,
+    };
+    self.smoothed_at = Some(self.sample_count);
+    
+    let attack = (-elapsed_ms / smoothing.attack_ms).exp() as f64;
+    let release = (-elapsed_ms / smoothing.release_ms).exp() as f64;
+    smooth(&mut self.smoothed, &self.freq_sum, n, attack, release);
+    if let Some(right) = self.right.as_mut() {
+      smooth(&mut right.smoothed, &right.freq_sum, n, attack, release);
+    }
+  }
+
+  // Takes the frame about to be drawn into the noise floors.
+  fn update_noise_floor(&mut self) {
+    let mut noise = match self.noise.take() {
+      Some(noise) => noise,
+      None => return,
+    };
+    
+    let rate = self.sample_rate * self.decimation.factor() as f32;
+    let elapsed_s = match noise.updated_at {
+      Some(at) => (self.sample_count - at) as f32 / rate,
+      None => 0.0,
+    };
+    
+    if let Some(frame) = self.display_frame() {
+      noise.updated_at = Some(self.sample_count);
+      noise.left.update(frame.len(), |bin| frame.magnitude(bin), elapsed_s);
+      if let Some(right) = self.right_frame() {
+        noise.right.update(right.len(), |bin| right.magnitude(bin), elapsed_s);
+      }
+      if let Some(small) = self.small_frame() {
+        noise.small.update(small.len(), |bin| small.magnitude(bin), elapsed_s);
+      }
+    }
+    
+    self.noise = Some(noise);
+  }
+
+  // Folds all octaves into 12 pitch classes, drawn as 12 bands starting
+  // with C. The frame is the one `draw_frame` would draw.
+  pub fn draw_chroma(&mut self, line: Line) -> Result<(),Error> {
+    self.update_gain();
+    self.update_smoothing();
+    self.update_noise_floor();
+    if self.skipping_silence() {
+      self.draw_background(line);
+    } else if self.quality == Quality::Minimal {
+      self.draw_level_bar(line, false);
+    } else if let Some(frame) = self.display_frame() {
+      self.draw_chroma_of(frame, line);
+    }
+    self.capture_history();
+    self.end_frame();
+    Ok(())
+  }
+
+  fn draw_chroma_of(&self, frame: Frame, line: Line) {
+    let from_hz = self.frequency_at(0.0);
+    let to_hz = self.frequency_at(1.0);
+    
+    let mut chroma = [0.0; 12];
+    for (hz,magnitude) in frame.iter() {
+      if hz < from_hz || hz > to_hz {
+        continue;
+      }
+      
+      let key = to_piano_key(hz, self.tuning_a4).round() as i32;
+      chroma[(key - 4).rem_euclid(12) as usize] += magnitude;
+    }
+    
+    let max = chroma.iter().cloned().fold(0.0, f32::max);
+    if max > 0.0 {
+      for c in chroma.iter_mut() {
+        *c /= max;
+      }
+    }
+    
+    for mut pixel in line.data.iter_mut() {
+      let v = chroma[pixel.x * 12 / line.len];
+      let alpha = match self.alpha {
+        Alpha::Opaque => u8::max_value(),
+        Alpha::Intensity => to_byte(v),
+      };
+      pixel.set(self.color_map.color(self.adjust(v)), alpha);
+    }
+  }
+
+  // Keeps the last `frames` drawn frames, reduced to `HISTORY_BINS`
+  // maximums over the frequency range, the newest ones if there were more.
+  pub fn enable_history(&mut self, frames: usize) -> Result<(),Error> {
+    if frames == 0 {
+      return Err(invalid("frames", 0.0));
+    }
+    
+    let size = frames * HISTORY_BINS;
+    match self.history.as_mut() {
+      Some(history) => history.frames.resize(size, 0.0),
+      None => self.history = Some( History {
+        frames: Ring::new(size, 0.0),
+        row: vec![0.0; HISTORY_BINS],
+      }),
+    }
+    Ok(())
+  }
+
+  pub fn disable_history(&mut self) {
+    self.history = None;
+  }
+
+  // Captured frames from the oldest to the newest, none without history.
+  pub fn history(&self) -> impl Iterator<Item=&[f32]> {
+    let frames = self.history.as_ref().map_or(&[][..], |history| {
+      &history.frames[history.frames.capacity() - history.frames.len() ..]
+    });
+    frames.chunks(HISTORY_BINS)
+  }
+
+  fn capture_history(&mut self) {
+    if self.quality != Quality::Full {
+      return;
+    }
+    
+    let mut history = match self.history.take() {
+      Some(history) => history,
+      None => return,
+    };
+    
+    if let Some(frame) = self.frame() {
+      let dx = 0.5 * pixel_width(HISTORY_BINS);
+      for (i,m) in history.row.iter_mut().enumerate() {
+        let x = pixel_position(i, HISTORY_BINS);
+        *m = frame
+          .reduce(self.frequency_at(x - dx), self.frequency_at(x + dx), Reduce::Max)
+          .unwrap_or_else(|| frame.magnitude_at(self.frequency_at(x)));
+      }
+      history.frames.extend_from_slice(&history.row);
+    }
+    
+    self.history = Some(history);
+  }
+
+  // Moving averages carry over to the next frame.
+  fn end_frame(&mut self) {
+    self.frame_count = self.frame_count.wrapping_add(1);
+    self.stats.frames_drawn = self.stats.frames_drawn.wrapping_add(1);
+    self.frame_silent = self.skipping_silence();
+    
+    if self.averaging_decay(self.effective_hop()).is_some() {
+      self.freq_n = 0;
+      if let Some(small) = self.small.as_mut() {
+        small.freq_n = 0;
+      }
+    } else {
+      self.clear_sums();
+    }
+  }
+
+  // Factor the peaks fall by per FFT, for FFTs every `hop` samples.
+  fn peak_decay(&self, hop: usize) -> f64 {
+    let rate = self.sample_rate * self.decimation.factor() as f32;
+    let db = self.peak_decay_db_s.unwrap_or(0.0) * hop as f32 / rate;
+    10.0_f64.powf(-db as f64 / 20.0)
+  }
+
+  // Weight of the moving average per FFT, for FFTs every `hop` samples.
+  fn averaging_decay(&self, hop: usize) -> Option<f32> {
+    if self.accumulate != Accumulate::Average {
+      return None;
+    }
+    
+    self.averaging_window_ms.map(|ms| {
+      let rate = self.sample_rate * self.decimation.factor() as f32;
+      let hop_ms = 1000.0 * hop as f32 / rate;
+      (-hop_ms / ms).exp()
+    })
+  }
+
+  // Also drops the peaks, which carry over to the next frame otherwise.
+  fn clear_accumulator(&mut self) {
+    self.clear_sums();
+    for peak in self.peaks.iter_mut() {
+      *peak = 0.0;
+    }
+    if let Some(right) = self.right.as_mut() {
+      for peak in right.peaks.iter_mut() {
+        *peak = 0.0;
+      }
+    }
+  }
+
+  fn clear_sums(&mut self) {
+    self.freq_n = 0;
+    for sum in self.freq_sum.iter_mut() {
+      *sum = 0.0;
+    }
+    
+    if let Some(small) = self.small.as_mut() {
+      small.freq_n = 0;
+      for sum in small.freq_sum.iter_mut() {
+        *sum = 0.0;
+      }
+    }
+    
+    if let Some(right) = self.right.as_mut() {
+      for sum in right.freq_sum.iter_mut() {
+        *sum = 0.0;
+      }
+    }
+  }
+
+  // Inverse of `frequency_at`.
+  fn position_at(&self, hz: f32) -> f32 {
+    match self.scale {
+      FrequencyScale::PianoKey => {
+        let keys = self.to - self.from + 1.0;
+        (to_piano_key(hz, self.tuning_a4) - self.from + 0.5) / keys
+      },
+      FrequencyScale::Mel => {
+        let from = to_mel(self.from);
+        (to_mel(hz) - from) / (to_mel(self.to) - from)
+      },
+      FrequencyScale::LinearHz => (hz - self.from) / (self.to - self.from),
+      FrequencyScale::LogHz => (hz / self.from).ln() / (self.to / self.from).ln(),
+      FrequencyScale::Bark => {
+        let from = to_bark(self.from);
+        (to_bark(hz) - from) / (to_bark(self.to) - from)
+      },
+    }
+  }
+
+  // Frequency at `x`, from 0 (start of the range) to 1 (end of the range).
+  fn frequency_at(&self, x: f32) -> f32 {
+    match self.scale {
+      FrequencyScale::PianoKey => {
+        let keys = self.to - self.from + 1.0;
+        from_piano_key(x * keys + self.from - 0.5, self.tuning_a4)
+      },
+      FrequencyScale::Mel => {
+        let from = to_mel(self.from);
+        from_mel(from + x * (to_mel(self.to) - from))
+      },
+      FrequencyScale::LinearHz => self.from + x * (self.to - self.from),
+      FrequencyScale::LogHz => self.from * (self.to / self.from).powf(x),
+      FrequencyScale::Bark => {
+        let from = to_bark(self.from);
+        from_bark(from + x * (to_bark(self.to) - from))
+      },
+    }
+  }
+}
+
+impl FrameData {
+  pub fn to_json(&self) -> String {
+    let mut json = String::new();
+    write!(json, "{{\"sample_rate\":{},", json_number(self.sample_rate)).unwrap();
+    write!(json, "\"fft_size\":{},", self.fft_size).unwrap();
+    write!(json, "\"decimation\":{},", self.decimation).unwrap();
+    json.push_str("\"bins\":[");
+
+    for (i,bin) in self.bins.iter().enumerate() {
+      if i > 0 {
+        json.push(',');
+      }
+      write!( json, "{{\"hz\":{},\"magnitude\":{}}}",
+              json_number(bin.hz), json_number(bin.magnitude) ).unwrap();
+    }
+    json.push(']');
+    
+    if let Some(floor) = self.noise_floor.as_ref() {
+      json.push_str(",\"noise_floor\":[");
+      for (i,x) in floor.iter().enumerate() {
+        if i > 0 {
+          json.push(',');
+        }
+        json.push_str(&json_number(*x));
+      }
+      json.push(']');
+    }
+    
+    json.push('}');
+    json
+  }
+}
+
+impl<'a> Frame<'a> {
+  pub fn len(&self) -> usize {
+    self.sum.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.sum.is_empty()
+  }
+
+  // Above the noise floor under noise reduction, never below zero.
+  pub fn magnitude(&self, bin: usize) -> f32 {
+    let magnitude = (self.sum[bin] / self.n as f64) as f32;
+    match self.floor.get(bin) {
+      Some(floor) => (magnitude - self.strength * floor).max(0.0),
+      None => magnitude,
+    }
+  }
+
+  // Zero without noise reduction.
+  pub fn noise_floor(&self, bin: usize) -> f32 {
+    self.floor.get(bin).cloned().unwrap_or(0.0)
+  }
+
+  // Subtracts the floor of `noise` from the magnitudes, unless it is the
+  // floor of frames of another size.
+  fn minus_noise( self,
+                  noise: Option<&'a NoiseReduction>,
+                  floor: impl Fn(&'a NoiseReduction) -> &'a NoiseFloor
+                ) -> Frame<'a> {
+    match noise {
+      Some(noise) if floor(noise).floor().len() == self.len() => Frame {
+        floor: floor(noise).floor(),
+        strength: noise.strength,
+        ..self
+      },
+      _ => Frame { floor: &[], ..self },
+    }
+  }
+
+  pub fn frequency(&self, bin: usize) -> f32 {
+    (bin + 1) as f32 * self.sample_rate / self.fft_len as f32
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item=(f32,f32)> + '_ {
+    (0 .. self.len()).map(move |bin| {
+      (self.frequency(bin), self.magnitude(bin))
+    })
+  }
+
+  pub fn copy_to(&self, out: &mut [f32]) {
+    for (bin,dst) in out.iter_mut().enumerate().take(self.len()) {
+      *dst = self.magnitude(bin);
+    }
+  }
+
+  // Linear interpolation between the two bins around `hz`.
+  pub fn magnitude_at(&self, hz: f32) -> f32 {
+    let i = self.position(hz).max(0.0).min(self.len() as f32 - 1.0);
+    
+    let i0 = i.floor() as usize;
+    let i1 = i.ceil()  as usize;
+    let di = i.fract();
+
+    self.magnitude(i0) * (1.0 - di) + self.magnitude(i1) * di
+  }
+
+  // Combines the bins between `from_hz` and `to_hz`, `None` if they span
+  // less than one bin.
+  pub fn reduce(&self, from_hz: f32, to_hz: f32, reduce: Reduce) -> Option<f32> {
+    let from = self.position(from_hz);
+    let to = self.position(to_hz);
+    
+    if to - from < 1.0 {
+      return None;
+    }
+    
+    let i0 = from.ceil().max(0.0) as usize;
+    let i1 = (to.floor() as usize).min(self.len() - 1);
+    
+    if to < 0.0 || i0 > i1 {
+      return None;
+    }
+    
+    let bins = (i0 ..= i1).map(|bin| self.magnitude(bin));
+    
+    Some( match reduce {
+      Reduce::Mean => bins.sum::<f32>() / (i1 - i0 + 1) as f32,
+      Reduce::Max  => bins.fold(0.0, f32::max),
+    })
+  }
+
+  // Magnitude below which a fraction `p` of the bins lie, found by
+  // bisection so as not to sort the bins.
+  pub fn percentile(&self, p: f32) -> f32 {
+    let target = p * self.len() as f32;
+    let mut low = 0.0;
+    let mut high = (0 .. self.len()).map(|bin| self.magnitude(bin))
+      .fold(0.0, f32::max);
+    
+    for _ in 0 .. 24 {
+      let mid = 0.5 * (low + high);
+      let below = (0 .. self.len())
+        .filter(|&bin| self.magnitude(bin) < mid)
+        .count();
+      if below as f32 > target {
+        high = mid;
+      } else {
+        low = mid;
+      }
+    }
+    
+    high
+  }
+
+  // Frequency and magnitude of the peak at `bin`, fitting a parabola
+  // through its neighbors.
+  fn refine(&self, bin: usize) -> (f32,f32) {
+    let magnitude = self.magnitude(bin);
+    
+    let (offset,magnitude) = if bin > 0 && bin + 1 < self.len() {
+      let a = self.magnitude(bin - 1);
+      let c = self.magnitude(bin + 1);
+      let d = a - 2.0 * magnitude + c;
+      if d < 0.0 {
+        let p = 0.5 * (a - c) / d;
+        (p, magnitude - 0.25 * (a - c) * p)
+      } else {
+        (0.0, magnitude)
+      }
+    } else {
+      (0.0, magnitude)
+    };
+    
+    let bin_width = self.sample_rate / self.fft_len as f32;
+    (self.frequency(bin) + offset * bin_width, magnitude)
+  }
+
+  // Fractional bin index of `hz`.
+  fn position(&self, hz: f32) -> f32 {
+    hz * self.fft_len as f32 / self.sample_rate - 1.0
+  }
+}
+
+impl SpectrogramBuilder {
+  pub fn new() -> SpectrogramBuilder {
+    SpectrogramBuilder {
+      config: SpectrogramConfig::default(),
+    }
+  }
+
+  pub fn from_config(config: SpectrogramConfig) -> SpectrogramBuilder {
+    SpectrogramBuilder {
+      config: config,
+    }
+  }
+
+  // Same as `buffer_size(2^(buffer_size_power - 1))`.
+  pub fn buffer_size_power(self, buffer_size_power: u32) -> Self {
+    self.buffer_size(power_to_size(buffer_size_power))
+  }
+
+  // The FFT runs on the last `buffer_size` decimated samples, any size
+  // from 2 to 2^19 being supported.
+  pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+    self.config.buffer_size = buffer_size;
+    self
+  }
+
+  pub fn key_range(self, from_key: f32, to_key: f32) -> Self {
+    self.frequency_range(FrequencyScale::PianoKey, from_key, to_key)
+  }
+
+  // Same as `key_range` with MIDI note numbers.
+  pub fn midi_range(self, from: u8, to: u8) -> Self {
+    self.key_range( from as f32 - MIDI_KEY_OFFSET,
+                    to as f32 - MIDI_KEY_OFFSET )
+  }
+
+  pub fn frequency_range( mut self,
+                          scale: FrequencyScale,
+                          from: f32,
+                          to: f32 ) -> Self {
+    self.config.scale = scale;
+    self.config.from = from;
+    self.config.to = to;
+    self
+  }
+
+  pub fn tuning_a4(mut self, tuning_a4: f32) -> Self {
+    self.config.tuning_a4 = tuning_a4;
+    self
+  }
+
+  pub fn boost(self, boost: f32) -> Self {
+    let curve = BoostCurve::Rational(boost);
+    self.amplitude_scale(AmplitudeScale::Boost(curve))
+  }
+
+  pub fn amplitude_scale(mut self, amplitude_scale: AmplitudeScale) -> Self {
+    self.config.amplitude_scale = amplitude_scale;
+    self
+  }
+
+  pub fn window(mut self, window: Window) -> Self {
+    self.config.window = window;
+    self
+  }
+
+  pub fn hop(mut self, hop: usize) -> Self {
+    self.config.hop = hop;
+    self
+  }
+
+  // The FFT runs on `zero_pad_factor` times more points than there are
+  // samples, the extra points being zeros. Either 1, 2 or 4.
+  pub fn zero_pad_factor(mut self, zero_pad_factor: usize) -> Self {
+    self.config.zero_pad_factor = zero_pad_factor;
+    self
+  }
+
+  pub fn decimation(mut self, decimation: Decimation) -> Self {
+    self.config.decimation = decimation;
+    self
+  }
+
+  pub fn channel_mode(mut self, channel_mode: ChannelMode) -> Self {
+    self.config.channel_mode = channel_mode;
+    self
+  }
+
+  pub fn output_mode(mut self, output_mode: OutputMode) -> Self {
+    self.config.output_mode = output_mode;
+    self
+  }
+
+  pub fn color_map(mut self, color_map: ColorMap) -> Self {
+    self.config.color_map = color_map;
+    self
+  }
+
+  pub fn accumulate(mut self, accumulate: Accumulate) -> Self {
+    self.config.accumulate = accumulate;
+    self
+  }
+
+  pub fn reduce(mut self, reduce: Reduce) -> Self {
+    self.config.reduce = reduce;
+    self
+  }
+
+  pub fn alpha(mut self, alpha: Alpha) -> Self {
+    self.config.alpha = alpha;
+    self
+  }
+
+  pub fn build(&self) -> Result<Spectrogram,Error> {
+    self.config.check()?;
+    Ok(Spectrogram::from_config(&self.config))
+  }
+}
+
+impl SpectrogramConfig {
+  fn check(&self) -> Result<(),Error> {
+    if self.buffer_size < MIN_BUFFER_SIZE
+      || self.buffer_size > MAX_BUFFER_SIZE {
+        return Err(invalid("buffer_size", self.buffer_size as f32));
+      }
+
+    check_range(self.scale, self.from, self.to)?;
+
+    check_tuning_a4(self.tuning_a4)?;
+
+    check_amplitude_scale(self.amplitude_scale)?;
+
+    if self.hop == 0 {
+      return Err(invalid("hop", 0.0));
+    }
+
+    if ![1,2,4].contains(&self.zero_pad_factor) {
+      return Err(invalid("zero_pad_factor", self.zero_pad_factor as f32));
+    }
+
+    Ok(())
+  }
+}
+
+impl Default for SpectrogramBuilder {
+  fn default() -> SpectrogramBuilder { SpectrogramBuilder::new() }
+}
+
+impl Default for SpectrogramConfig {
+  fn default() -> SpectrogramConfig {
+    SpectrogramConfig {
+      buffer_size: 256,
+      scale: FrequencyScale::PianoKey,
+      from: 1.0,
+      to: 88.0,
+      tuning_a4: 440.0,
+      amplitude_scale: AmplitudeScale::Boost(BoostCurve::Rational(0.0)),
+      window: Window::default(),
+      hop: 1,
+      zero_pad_factor: 1,
+      decimation: Decimation::Half,
+      channel_mode: ChannelMode::Left,
+      output_mode: OutputMode::Mute,
+      color_map: ColorMap::default(),
+      accumulate: Accumulate::Average,
+      reduce: Reduce::Mean,
+      alpha: Alpha::Opaque,
+    }
+  }
+}
+
+impl Default for Grid {
+  fn default() -> Grid {
+    Grid {
+      octave_color: [128, 128, 128, 96],
+      c_color: [128, 128, 128, 48],
+    }
+  }
+}
+
+impl Default for Backend {
+  fn default() -> Backend {
+    Backend::Fft
+  }
+}
+
+impl Default for Quality {
+  fn default() -> Quality {
+    Quality::Full
+  }
+}
+
+impl Default for SilenceSkip {
+  fn default() -> SilenceSkip {
+    SilenceSkip {
+      threshold_db: -70.0,
+      buffers: 4,
+    }
+  }
+}
+
+// A quarter of the default buffer size.
+impl Default for DualResolution {
+  fn default() -> DualResolution {
+    DualResolution {
+      buffer_size: 64,
+      hop: 32,
+      crossover_key: 40.0,
+    }
+  }
+}
+
+impl NoiseGate {
+  fn magnitude(self, frame: &Frame) -> f32 {
+    match self {
+      NoiseGate::Magnitude(m) => m,
+      NoiseGate::Decibel(db) => 10.0_f32.powf(db / 20.0),
+      NoiseGate::Auto(p) => frame.percentile(p),
+    }
+  }
+}
+
+impl ChannelSelect {
+  // Channels the input must have at least.
+  fn required(&self) -> usize {
+    match self {
+      ChannelSelect::Index(i) => i + 1,
+      ChannelSelect::Mix => 1,
+      ChannelSelect::MixOf(channels) => channels.iter().max().map_or(0, |i| i + 1),
+    }
+  }
+
+  // Value of a frame of `channels` channels given by `sample`.
+  fn select(&self, channels: usize, sample: impl Fn(usize) -> f32) -> f32 {
+    match self {
+      ChannelSelect::Index(i) => sample(*i),
+      ChannelSelect::Mix =>
+        (0 .. channels).map(&sample).sum::<f32>() / channels as f32,
+      ChannelSelect::MixOf(list) =>
+        list.iter().map(|i| sample(*i)).sum::<f32>() / list.len() as f32,
+    }
+  }
+}
+
+impl BoostCurve {
+  fn apply(self, value: f32) -> f32 {
+    let v = value.max(0.0).min(1.0);
+    match self {
+      BoostCurve::Rational(by) => ((by + 1.0) * v) / (by * v + 1.0),
+      BoostCurve::Gamma(gamma) => v.powf(1.0 / gamma),
+      BoostCurve::Log { floor } => (v.max(floor) / floor).ln() / -floor.ln(),
+      BoostCurve::Sigmoid { midpoint, steepness } => {
+        let sigmoid = |x: f32| 1.0 / (1.0 + (-steepness * (x - midpoint)).exp());
+        let (low, high) = (sigmoid(0.0), sigmoid(1.0));
+        (sigmoid(v) - low) / (high - low)
+      },
+    }
+  }
+
+  // Only these parameters give increasing curves.
+  fn check(self) -> Result<(),Error> {
+    match self {
+      BoostCurve::Rational(by) if !(by >= 0.0) => Err(invalid("boost", by)),
+      BoostCurve::Gamma(gamma) if !(gamma > 0.0) => Err(invalid("gamma", gamma)),
+      BoostCurve::Log { floor } if !(floor > 0.0 && floor < 1.0) =>
+        Err(invalid("floor", floor)),
+      BoostCurve::Sigmoid { midpoint, .. } if !midpoint.is_finite() =>
+        Err(invalid("midpoint", midpoint)),
+      BoostCurve::Sigmoid { steepness, .. } if !(steepness > 0.0) =>
+        Err(invalid("steepness", steepness)),
+      _ => Ok(()),
+    }
+  }
+}
+
+impl AmplitudeScale {
+  fn apply(self, magnitude: f32) -> f32 {
+    match self {
+      AmplitudeScale::Boost(curve) => curve.apply(magnitude),
+      AmplitudeScale::Decibel { floor_db, ceil_db } => {
+        // NaNs are drawn as silence.
+        let db = 20.0 * magnitude.max(std::f32::MIN_POSITIVE).log10();
+        ((db - floor_db) / (ceil_db - floor_db)).max(0.0).min(1.0)
+      }
+    }
+  }
+}
+
+
+// Fails to compile if `Spectrogram` can no longer move to another thread,
+// e.g. to analyze in a worker.
+#[allow(dead_code)]
+fn assert_send() {
+  fn is_send<T: Send>() {}
+  is_send::<Spectrogram>();
+  is_send::<Ring<Complex<f32>>>();
+}
+
+// Windowed FFT of the newest samples, or only the bins `goertzel_bins`.
+fn transform( samples: &[Complex<f32>],
+              fft: &dyn FFT<f32>,
+              coefficients: &[f32],
+              goertzel_bins: Option<Range<usize>>,
+              input: &mut [Complex<f32>],
+              output: &mut [Complex<f32>] ) {
+  let samples = &samples[samples.len() - coefficients.len() ..];
+  
+  for ((x,s),w) in input.iter_mut().zip(samples).zip(coefficients) {
+    *x = s * w;
+  }
+
+  if let Some(bins) = goertzel_bins {
+    goertzel(&input[.. coefficients.len()], bins, output);
+    return;
+  }
+
+  // The FFT uses its input as scratch space.
+  for x in input[coefficients.len() ..].iter_mut() {
+    *x = Complex::zero();
+  }
+  
+  fft.process(input, output);
+}
+
+// Moves `smoothed` towards the magnitudes summed `n` times in `sum`,
+// with the weight `attack` of the old value for rising magnitudes and
+// `release` for falling ones.
+fn smooth( smoothed: &mut [f64],
+           sum: &[f64],
+           n: usize,
+           attack: f64,
+           release: f64 ) {
+  for (s,sum) in smoothed.iter_mut().zip(sum) {
+    let value = sum / n as f64;
+    let a = if value > *s { attack } else { release };
+    *s = a * *s + (1.0 - a) * value;
+  }
+}
+
+// Filters `value`, returning a sample once the decimator has one.
+fn condition( value: f32,
+              high_pass: Option<&mut HighPass>,
+              pre_emphasis: Option<&mut PreEmphasis>,
+              decimator: &mut Decimator ) -> Option<f32> {
+  let value = match high_pass {
+    Some(h) => h.push(value),
+    None => value,
+  };
+  
+  let value = match pre_emphasis {
+    Some(p) => p.push(value),
+    None => value,
+  };
+  
+  decimator.push(value)
+}
+
+// Accumulates the scaled magnitudes of the non-DC bins of `output`,
+// refined if there is a reassigner, holding them in `peaks` decayed by
+// `peak_decay` unless there are none.
+fn accumulate_frame( output: &[Complex<f32>],
+                     refine: Option<&mut Reassigner>,
+                     accumulation: &Accumulation,
+                     sums: &mut [f64],
+                     peaks: &mut [f64] ) {
+  let Accumulation {
+    hop, scale, weights, mode, decay, peak_decay,
+  } = *accumulation;
+  
+  // No weights is no weighting.
+  let weight = |i: usize| weights.get(i).cloned().unwrap_or(1.0);
+  let mut add = |i: usize, m: f32, sum: &mut f64| {
+    accumulate(sum, m, mode, decay);
+    if let Some(peak) = peaks.get_mut(i) {
+      *peak *= peak_decay;
+      accumulate(peak, m, Accumulate::PeakHold, None);
+    }
+  };
+  
+  match refine {
+    Some(refine) => {
+      let refined = refine.reassign(output, hop, scale);
+      for (i,(m,sum)) in refined.iter().zip(sums).enumerate() {
+        add(i, weight(i) * m, sum);
+      }
+    },
+    None => {
+      let bins = output[1 .. output.len() / 2].iter();
+      for (i,(bin,sum)) in bins.zip(sums).enumerate() {
+        add(i, weight(i) * scale * bin.norm(), sum);
+      }
+    },
+  }
+}
+
+fn accumulate( sum: &mut f64,
+               magnitude: f32,
+               accumulate: Accumulate,
+               decay: Option<f64> ) {
+  // Finite samples can still overflow the FFT.
+  let value = if magnitude.is_finite() { magnitude as f64 } else { 0.0 };
+  match (accumulate, decay) {
+    (Accumulate::Average, None) => *sum += value,
+    (Accumulate::Average, Some(a)) => *sum = a * *sum + (1.0 - a) * value,
+    (Accumulate::PeakHold, _) => *sum = sum.max(value),
+  }
+}
+
+// Position of pixel `x` along a line of `len` pixels, from 0 to 1. A
+// single pixel sits in the middle of the range.
+fn pixel_position(x: usize, len: usize) -> f32 {
+  if len > 1 {
+    x as f32 / (len - 1) as f32
+  } else {
+    0.5
+  }
+}
+
+fn pixel_width(len: usize) -> f32 {
+  if len > 1 {
+    1.0 / (len - 1) as f32
+  } else {
+    1.0
+  }
+}
+
+// 2^(power - 1), saturating.
+fn power_to_size(power: u32) -> usize {
+  match power {
+    0 => 0,
+    p => 2_usize.checked_pow(p - 1).unwrap_or(std::usize::MAX),
+  }
+}
+
+// Weights of `len` bins centered on `bin_hz(bin)`, none without a
+// weighting or a tilt.
+fn weights( weighting: Weighting,
+            tilt_db_per_octave: f32,
+            pivot_hz: f32,
+            len: usize,
+            bin_hz: impl Fn(usize) -> f32 ) -> Vec<f32> {
+  if tilt_db_per_octave == 0.0 {
+    return weighting.table(len, bin_hz);
+  }
+  
+  (0 .. len).map(|bin| {
+    let hz = bin_hz(bin);
+    weighting.gain(hz) * tilt_gain(hz, pivot_hz, tilt_db_per_octave)
+  }).collect()
+}
+
+fn invalid(name: &'static str, value: f32) -> Error {
+  Error::InvalidConfig { name: name, value: value }
+}
+
+fn check_range(scale: FrequencyScale, from: f32, to: f32) -> Result<(),Error> {
+  match scale {
+    FrequencyScale::PianoKey if !(from <= to) => Err(invalid("to", to)),
+    FrequencyScale::PianoKey => Ok(()),
+    _ if !(from < to) => Err(invalid("to", to)),
+    FrequencyScale::LogHz if !(from > 0.0) => Err(invalid("from", from)),
+    FrequencyScale::Mel | FrequencyScale::LinearHz | FrequencyScale::Bark
+      if !(from >= 0.0) =>
+      Err(invalid("from", from)),
+    _ => Ok(()),
+  }
+}
+
+// Ranges in Hz can't end above the Nyquist frequency of the decimated
+// sample rate.
+fn check_nyquist( scale: FrequencyScale,
+                  to: f32,
+                  sample_rate: f32 ) -> Result<(),Error> {
+  if scale != FrequencyScale::PianoKey && to > sample_rate / 2.0 {
+    Err(invalid("to", to))
+  } else {
+    Ok(())
+  }
+}
+
+fn check_tuning_a4(tuning_a4: f32) -> Result<(),Error> {
+  if tuning_a4 > 0.0 && tuning_a4.is_finite() {
+    Ok(())
+  } else {
+    Err(invalid("tuning_a4", tuning_a4))
+  }
+}
+
+fn check_amplitude_scale(scale: AmplitudeScale) -> Result<(),Error> {
+  match scale {
+    AmplitudeScale::Boost(curve) => curve.check(),
+    AmplitudeScale::Decibel { floor_db, .. } if !floor_db.is_finite() =>
+      Err(invalid("floor_db", floor_db)),
+    AmplitudeScale::Decibel { floor_db, ceil_db }
+      if !(floor_db < ceil_db && ceil_db.is_finite()) =>
+      Err(invalid("ceil_db", ceil_db)),
+    _ => Ok(()),
+  }
+}
+
+// Silences magnitudes below `gate`, fading them back in up to
+// `GATE_KNEE` times the gate.
+fn apply_gate(magnitude: f32, gate: f32) -> f32 {
+  if magnitude >= GATE_KNEE * gate {
+    return magnitude;
+  }
+  
+  let t = ((magnitude - gate) / ((GATE_KNEE - 1.0) * gate)).max(0.0);
+  magnitude * t * t * (3.0 - 2.0 * t)
+}
+
+// `over` with an opacity of `alpha` over `under`.
+fn blend(under: u8, over: u8, alpha: u8) -> u8 {
+  let a = alpha as u32;
+  ((under as u32 * (255 - a) + over as u32 * a + 127) / 255) as u8
+}
+
+// JSON has no infinities nor NaNs.
+fn json_number(value: f32) -> String {
+  if value.is_finite() {
+    value.to_string()
+  } else {
+    "null".to_string()
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use std::alloc::GlobalAlloc;
+  use std::alloc::Layout;
+  use std::alloc::System;
+  use std::cell::Cell;
+  use std::f32::consts::PI;
+
+  use crate::audio::BufferData;
+
+  use super::*;
+
+  fn sine(hz: f32, len: usize) -> Vec<f32> {
+    (0 .. len).map(|i| 0.5 * (2.0 * PI * hz * i as f32 / 48000.0).sin()).collect()
+  }
+
+  // Share of the magnitudes more than 3 bins away from the peak.
+  fn leakage(window: Window) -> f32 {
+    let mut spectrogram = SpectrogramBuilder::new()
+      .buffer_size(2048)
+      .decimation(Decimation::None)
+      .window(window)
+      .hop(2048)
+      .build().unwrap();
+    spectrogram.process_planar(&sine(440.0, 2048), None, 48000.0).unwrap();
+    
+    let frame = spectrogram.frame().unwrap();
+    let magnitudes = (0 .. frame.len()).map(|bin| frame.magnitude(bin)).collect::<Vec<_>>();
+    let peak = (0 .. magnitudes.len())
+      .max_by(|&a,&b| magnitudes[a].partial_cmp(&magnitudes[b]).unwrap())
+      .unwrap();
+    
+    let total = magnitudes.iter().sum::<f32>();
+    let far = magnitudes.iter().enumerate()
+      .filter(|&(bin,_)| (bin as isize - peak as isize).abs() > 3)
+      .map(|(_,m)| m)
+      .sum::<f32>();
+    far / total
+  }
+
+  #[test]
+  fn hann_leaks_less_than_rect() {
+    let rect = leakage(Window::Rect);
+    let hann = leakage(Window::Hann);
+    assert!(hann < rect / 4.0, "{} {}", hann, rect);
+  }
+
+  #[test]
+  fn an_fft_per_hop() {
+    for &hop in [1, 64, 512, 4096].iter() {
+      let mut spectrogram = SpectrogramBuilder::new().hop(hop).build().unwrap();
+      // The first FFT waits for a full buffer.
+      let warm_up = sine(440.0, 4 * spectrogram.buffer_size());
+      spectrogram.process_planar(&warm_up, None, 48000.0).unwrap();
+      spectrogram.reset_stats();
+      
+      for _ in 0 .. 4 {
+        spectrogram.process_planar(&sine(440.0, 4096), None, 48000.0).unwrap();
+      }
+      assert_eq!(spectrogram.stats().ffts, 4 * 4096 / hop as u64);
+    }
+  }
+
+  // Name of the setting `build` rejects.
+  fn rejected(builder: SpectrogramBuilder) -> &'static str {
+    match builder.build() {
+      Err(Error::InvalidConfig { name, .. }) => name,
+      Err(error) => panic!("unexpected error: {}", error),
+      Ok(_) => panic!("accepted"),
+    }
+  }
+
+  #[test]
+  fn build_rejects() {
+    let new = SpectrogramBuilder::new;
+    let range = |scale, from, to| new().frequency_range(scale, from, to);
+    let boost = |curve| new().amplitude_scale(AmplitudeScale::Boost(curve));
+    let decibel = |floor_db, ceil_db| {
+      let scale = AmplitudeScale::Decibel { floor_db: floor_db, ceil_db: ceil_db };
+      new().amplitude_scale(scale)
+    };
+    let sigmoid = |midpoint, steepness| {
+      boost(BoostCurve::Sigmoid { midpoint: midpoint, steepness: steepness })
+    };
+    
+    assert_eq!(rejected(new().buffer_size_power(0)), "buffer_size");
+    assert_eq!(rejected(new().buffer_size(1)), "buffer_size");
+    assert_eq!(rejected(new().buffer_size(MAX_BUFFER_SIZE + 1)), "buffer_size");
+    
+    assert_eq!(rejected(new().key_range(60.0, 40.0)), "to");
+    assert_eq!(rejected(new().key_range(std::f32::NAN, 40.0)), "to");
+    assert_eq!(rejected(range(FrequencyScale::LinearHz, 100.0, 100.0)), "to");
+    assert_eq!(rejected(range(FrequencyScale::LogHz, 0.0, 100.0)), "from");
+    assert_eq!(rejected(range(FrequencyScale::Mel, -1.0, 100.0)), "from");
+    assert_eq!(rejected(range(FrequencyScale::Bark, -1.0, 10.0)), "from");
+    
+    assert_eq!(rejected(new().tuning_a4(0.0)), "tuning_a4");
+    assert_eq!(rejected(new().tuning_a4(std::f32::INFINITY)), "tuning_a4");
+    
+    assert_eq!(rejected(new().boost(-1.0)), "boost");
+    assert_eq!(rejected(boost(BoostCurve::Gamma(0.0))), "gamma");
+    assert_eq!(rejected(boost(BoostCurve::Log { floor: 1.0 })), "floor");
+    assert_eq!(rejected(sigmoid(std::f32::NAN, 1.0)), "midpoint");
+    assert_eq!(rejected(sigmoid(0.5, 0.0)), "steepness");
+    assert_eq!(rejected(decibel(std::f32::NEG_INFINITY, 0.0)), "floor_db");
+    assert_eq!(rejected(decibel(-20.0, -60.0)), "ceil_db");
+    
+    assert_eq!(rejected(new().hop(0)), "hop");
+    assert_eq!(rejected(new().zero_pad_factor(3)), "zero_pad_factor");
+    
+    assert!(new().build().is_ok());
+    assert!(Spectrogram::try_new(0, 1.0, 88.0, 0.0).is_err());
+  }
+
+  fn peek(spectrogram: &Spectrogram, len: usize) -> Vec<u8> {
+    let mut rgba = vec![0; 4 * len];
+    spectrogram.draw_frame_peek( Line {
+      len: len,
+      data: &mut LineData::new(&mut rgba, PixelFormat::Rgba8),
+    }).unwrap();
+    rgba
+  }
+
+  #[test]
+  fn short_lines() {
+    // A4 in the lower half of the range, darkest in inverted grayscale.
+    let mut spectrogram = Spectrogram::new(10, 40.0, 70.0, 0.0);
+    spectrogram.set_reduce(Reduce::Max);
+    spectrogram.process_planar(&sine(440.0, 4096), None, 48000.0).unwrap();
+    
+    assert!(peek(&spectrogram, 0).is_empty());
+    
+    let center = peek(&spectrogram, 1);
+    assert_eq!(&center[..], &peek(&spectrogram, 3)[4 .. 8]);
+    
+    let ends = peek(&spectrogram, 2);
+    assert_eq!(&ends[.. 4], &center[..]);
+    assert!(ends[4] > center[0], "{:?} {:?}", ends, center);
+    assert_eq!(ends[7], u8::max_value());
+  }
+
+  #[test]
+  fn planar_lengths() {
+    let mut spectrogram = SpectrogramBuilder::new().build().unwrap();
+    let result = spectrogram.process_planar(&[0.0; 4], Some(&[0.0; 3]), 48000.0);
+    assert_eq!(result.err(), Some(Error::ChannelLengthMismatch { left: 4, right: 3 }));
+  }
+
+  #[test]
+  fn mono_is_duplicated_stereo() {
+    let modes = [
+      ChannelMode::Left, ChannelMode::Right, ChannelMode::Mix,
+      ChannelMode::Mid, ChannelMode::Side,
+    ];
+    
+    for &mode in modes.iter() {
+      let new = || SpectrogramBuilder::new().channel_mode(mode).build().unwrap();
+      
+      let mut mono = new();
+      let mut samples = sine(440.0, 4096);
+      mono.process( Buffer {
+        sample_rate: 48000.0,
+        data: &mut BufferData::mono(&mut samples),
+      }).unwrap();
+      
+      let mut stereo = new();
+      let mut left = sine(440.0, 4096);
+      let mut right = sine(440.0, 4096);
+      stereo.process( Buffer {
+        sample_rate: 48000.0,
+        data: &mut BufferData::new(&mut left, &mut right),
+      }).unwrap();
+      
+      assert_eq!(mono.freq_n, stereo.freq_n, "{:?}", mode);
+      assert_eq!(mono.freq_sum, stereo.freq_sum, "{:?}", mode);
+    }
+  }
+
+  #[test]
+  fn i16_within_an_lsb() {
+    let new = || {
+      SpectrogramBuilder::new()
+        .buffer_size(1024)
+        .decimation(Decimation::None)
+        .hop(1024)
+        .build().unwrap()
+    };
+    
+    let samples = sine(440.0, 2 * 4096);
+    let pcm = samples.iter()
+      .map(|x| (x * 32768.0).round() as i16)
+      .collect::<Vec<_>>();
+    
+    let mut float = new();
+    float.process_interleaved(&samples, 2, 48000.0).unwrap();
+    let mut int = new();
+    int.process_i16(&pcm, 2, 48000.0).unwrap();
+    
+    // Rounding is off by half an LSB at most, doubled by the scaling of
+    // the magnitudes.
+    let (a, b) = (float.frame().unwrap(), int.frame().unwrap());
+    for bin in 0 .. a.len() {
+      let error = (a.magnitude(bin) - b.magnitude(bin)).abs();
+      assert!(error <= 1.0 / 32768.0, "{} {}", bin, error);
+    }
+    
+    let result = int.process_i16(&pcm[.. 3], 2, 48000.0);
+    assert_eq!(result.err(), Some(Error::PartialFrame { channels: 2, remainder: 1 }));
+  }
+
+  // Counts the allocations of each thread, tests running in parallel.
+  struct Counting;
+
+  thread_local! {
+    static ALLOCATIONS: Cell<usize> = Cell::new(0);
+  }
+
+  unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+      let _ = ALLOCATIONS.try_with(|n| n.set(n.get() + 1));
+      System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+      System.dealloc(ptr, layout)
+    }
+  }
+
+  #[global_allocator]
+  static ALLOCATOR: Counting = Counting;
+
+  fn allocations() -> usize {
+    ALLOCATIONS.with(|n| n.get())
+  }
+
+  #[test]
+  fn no_allocations_after_warm_up() {
+    let mut spectrogram = SpectrogramBuilder::new()
+      .hop(64)
+      .window(Window::Hann)
+      .build().unwrap();
+    let samples = sine(440.0, 4096);
+    let mut rgba = vec![0; 4 * 300];
+    spectrogram.process_planar(&samples, None, 48000.0).unwrap();
+    spectrogram.draw_into_rgba(&mut rgba, 300).unwrap();
+    
+    let before = allocations();
+    for chunk in samples.chunks(128) {
+      spectrogram.process_planar(chunk, None, 48000.0).unwrap();
+      spectrogram.draw_into_rgba(&mut rgba, 300).unwrap();
+    }
+    assert_eq!(allocations(), before);
+  }
+
+  #[test]
+  fn no_drift_over_a_million_frames() {
+    let frames = 1_000_000;
+    let magnitude = 0.1;
+    
+    let mut sum = 0.0;
+    let mut sum_f32 = 0.0_f32;
+    for _ in 0 .. frames {
+      accumulate(&mut sum, magnitude, Accumulate::Average, None);
+      sum_f32 += magnitude;
+    }
+    
+    let error = |mean: f64| (mean / magnitude as f64 - 1.0).abs();
+    assert!(error(sum / frames as f64) < 1e-4);
+    assert!(error(sum_f32 as f64 / frames as f64) > 1e-3);
+  }
+
+  #[test]
+  fn two_bursts_two_onsets() {
+    let mut spectrogram = SpectrogramBuilder::new()
+      .window(Window::Hann)
+      .hop(64)
+      .build().unwrap();
+    
+    let mut samples = vec![0.0; 48000];
+    for &(start,hz) in [(6000, 440.0), (30000, 660.0)].iter() {
+      samples[start .. start + 8000].copy_from_slice(&sine(hz, 8000));
+    }
+    for chunk in samples.chunks(128) {
+      spectrogram.process_planar(chunk, None, 48000.0).unwrap();
+    }
+    
+    let onsets = spectrogram.take_onsets();
+    assert_eq!(onsets.len(), 2, "{:?}", onsets);
+    assert!(onsets[0].offset >= 6000 && onsets[0].offset < 7000, "{:?}", onsets);
+    assert!(onsets[1].offset >= 30000 && onsets[1].offset < 31000, "{:?}", onsets);
+    assert!(spectrogram.take_onsets().is_empty());
+  }
+
+  #[test]
+  fn reset_is_fresh() {
+    let new = || {
+      let mut spectrogram = SpectrogramBuilder::new()
+        .window(Window::Hann)
+        .build().unwrap();
+      spectrogram.set_high_pass(Some(20.0)).unwrap();
+      spectrogram.set_pre_emphasis(Some(0.97)).unwrap();
+      spectrogram
+    };
+    
+    let mut reset = new();
+    reset.process_planar(&sine(1000.0, 2048), None, 48000.0).unwrap();
+    reset.draw_into_rgba(&mut vec![0; 4 * 10], 10).unwrap();
+    reset.process_planar(&sine(1000.0, 700), None, 48000.0).unwrap();
+    reset.reset();
+    reset.process_planar(&sine(440.0, 2048), None, 48000.0).unwrap();
+    
+    let mut fresh = new();
+    fresh.process_planar(&sine(440.0, 2048), None, 48000.0).unwrap();
+    
+    assert_eq!(reset.freq_n, fresh.freq_n);
+    assert_eq!(reset.freq_sum, fresh.freq_sum);
+    
+    let (mut a, mut b) = (vec![0; 4 * 100], vec![0; 4 * 100]);
+    reset.draw_into_rgba(&mut a, 100).unwrap();
+    fresh.draw_into_rgba(&mut b, 100).unwrap();
+    assert_eq!(a, b);
+  }
+
+  #[test]
+  fn midi_range() {
+    let spectrogram = SpectrogramBuilder::new().midi_range(21, 108).build().unwrap();
+    assert_eq!(spectrogram.frequency_range(), (1.0, 88.0));
+  }
+
+  fn xorshift(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+  }
+
+  // Frames drawn and the newest spectrum of 10 s of a sweep over noise,
+  // processed in chunks of `sizes()` samples.
+  fn chunked( sizes: &mut dyn FnMut() -> usize
+             ) -> (Vec<FrameData>, Vec<Complex<f32>>) {
+    let mut phase = 0.0_f32;
+    let mut state = 3;
+    let samples = (0 .. 441000).map(|i| {
+      let hz = 50.0 * 160.0_f32.powf(i as f32 / 441000.0);
+      phase = (phase + hz / 44100.0).fract();
+      let noise = (xorshift(&mut state) >> 40) as f32 / (1 << 24) as f32 - 0.5;
+      0.5 * (2.0 * PI * phase).sin() + 0.2 * noise
+    }).collect::<Vec<_>>();
+    
+    let mut spectrogram = SpectrogramBuilder::new()
+      .buffer_size(2048)
+      .window(Window::Hann)
+      .hop(441)
+      .build().unwrap();
+    spectrogram.set_high_pass(Some(30.0)).unwrap();
+    spectrogram.set_pre_emphasis(Some(0.9)).unwrap();
+    spectrogram.set_refine(true);
+    spectrogram.set_dual_resolution(Some(DualResolution::default())).unwrap();
+    
+    let frames = Arc::new(std::sync::Mutex::new(Vec::new()));
+    let copies = frames.clone();
+    spectrogram.on_frame(1, move |frame| {
+      copies.lock().unwrap().push(frame.clone());
+    }).unwrap();
+    
+    let mut pos = 0;
+    while pos < samples.len() {
+      let chunk = &samples[pos .. pos + sizes().min(samples.len() - pos)];
+      spectrogram.process_planar(chunk, None, 44100.0).unwrap();
+      pos += chunk.len();
+    }
+    
+    let frames = frames.lock().unwrap().clone();
+    (frames, spectrogram.last_spectrum().to_vec())
+  }
+
+  #[test]
+  fn any_chunking() {
+    let (frames, spectrum) = chunked(&mut || 128);
+    
+    let mut state = 12345;
+    let (random_frames, random_spectrum) =
+      chunked(&mut || 1 + (xorshift(&mut state) % 4096) as usize);
+    
+    assert!(frames.len() > 900);
+    assert_eq!(frames.len(), random_frames.len());
+    for (i,(a,b)) in frames.iter().zip(random_frames.iter()).enumerate() {
+      assert!(a == b, "frame {}", i);
+    }
+    assert_eq!(spectrum, random_spectrum);
+  }
+
+  #[test]
+  fn minimal_chroma_is_the_level_bar() {
+    let draw = |chroma: bool| {
+      let mut spectrogram = SpectrogramBuilder::new().build().unwrap();
+      spectrogram.set_quality(Quality::Minimal);
+      spectrogram.process_planar(&sine(440.0, 4096), None, 48000.0).unwrap();
+      
+      let mut rgba = vec![0; 4 * 100];
+      let line = Line {
+        len: 100,
+        data: &mut LineData::new(&mut rgba, PixelFormat::Rgba8),
+      };
+      if chroma {
+        spectrogram.draw_chroma(line).unwrap();
+      } else {
+        spectrogram.draw_frame(line).unwrap();
+      }
+      rgba
+    };
+    assert_eq!(draw(true), draw(false));
+  }
+
+  fn keys(spectrogram: &Spectrogram) -> (f32,f32) {
+    spectrogram.frequency_range()
+  }
+
+  #[test]
+  fn zoom_keeps_the_center() {
+    let mut spectrogram = Spectrogram::new(9, 40.0, 60.0, 25.0);
+    spectrogram.zoom(45.0, 2.0).unwrap();
+    assert_eq!(keys(&spectrogram), (42.5, 52.5));
+    spectrogram.zoom(45.0, 0.5).unwrap();
+    assert_eq!(keys(&spectrogram), (40.0, 60.0));
+  }
+
+  #[test]
+  fn zoom_stops_at_the_minimum_span() {
+    let mut spectrogram = Spectrogram::new(9, 40.0, 60.0, 25.0);
+    spectrogram.zoom(50.0, 1000.0).unwrap();
+    assert_eq!(keys(&spectrogram), (48.5, 48.5 + MIN_ZOOM_SPAN_KEYS));
+  }
+
+  #[test]
+  fn zoom_out_stops_at_the_keyboard() {
+    let mut spectrogram = Spectrogram::new(9, 40.0, 60.0, 25.0);
+    spectrogram.zoom(50.0, 0.01).unwrap();
+    assert_eq!(keys(&spectrogram), (1.0, 88.0));
+  }
+
+  #[test]
+  fn zoom_near_the_ends_stays_within() {
+    let mut spectrogram = Spectrogram::new(9, 1.0, 20.0, 25.0);
+    spectrogram.zoom(1.0, 1000.0).unwrap();
+    assert_eq!(keys(&spectrogram), (1.0, 1.0 + MIN_ZOOM_SPAN_KEYS));
+    
+    spectrogram.set_key_range(70.0, 88.0).unwrap();
+    spectrogram.zoom(88.0, 1000.0).unwrap();
+    assert_eq!(keys(&spectrogram), (88.0 - MIN_ZOOM_SPAN_KEYS, 88.0));
+    
+    spectrogram.zoom(87.0, 0.5).unwrap();
+    let (from, to) = keys(&spectrogram);
+    assert_eq!(to, 88.0);
+    assert_eq!(to - from, 2.0 * MIN_ZOOM_SPAN_KEYS);
+  }
+
+  #[test]
+  fn pan_stops_at_both_ends() {
+    let mut spectrogram = Spectrogram::new(9, 40.0, 60.0, 25.0);
+    spectrogram.pan(-100.0).unwrap();
+    assert_eq!(keys(&spectrogram), (1.0, 21.0));
+    spectrogram.pan(100.0).unwrap();
+    assert_eq!(keys(&spectrogram), (68.0, 88.0));
+    spectrogram.pan(-7.0).unwrap();
+    assert_eq!(keys(&spectrogram), (61.0, 81.0));
+  }
+
+  #[test]
+  fn zoom_limits() {
+    let mut spectrogram = Spectrogram::new(9, 40.0, 60.0, 25.0);
+    assert!(spectrogram.set_zoom_limits(30.0, 32.0).is_err());
+    assert!(spectrogram.set_zoom_limits(30.0, std::f32::NAN).is_err());
+    spectrogram.set_zoom_limits(30.0, 50.0).unwrap();
+    spectrogram.pan(0.0).unwrap();
+    assert_eq!(keys(&spectrogram), (30.0, 50.0));
+    
+    assert!(spectrogram.zoom(40.0, 0.0).is_err());
+    assert!(spectrogram.zoom(std::f32::NAN, 2.0).is_err());
+    assert!(spectrogram.pan(std::f32::INFINITY).is_err());
+    assert_eq!(keys(&spectrogram), (30.0, 50.0));
+  }
+
+  // Two bins of the FFT of 512 points at 48 kHz, an octave apart.
+  fn octave_db(tilt_db_per_octave: f32) -> f32 {
+    let mut spectrogram = Spectrogram::new(9, 40.0, 87.0, 25.0);
+    spectrogram.set_tilt_db_per_octave(tilt_db_per_octave).unwrap();
+    
+    let samples = (0 .. 4096).map(|i| {
+      let t = i as f32 / 48000.0;
+      0.25 * ((2.0 * PI * 750.0 * t).sin() + (2.0 * PI * 1500.0 * t).sin())
+    }).collect::<Vec<_>>();
+    spectrogram.process_planar(&samples, None, 48000.0).unwrap();
+    
+    let db = |hz| spectrogram.value_at_hz(hz).unwrap().db;
+    db(1500.0) - db(750.0)
+  }
+
+  #[test]
+  fn tilt_per_octave() {
+    assert!(octave_db(0.0).abs() < 0.5);
+    assert!((octave_db(6.0) - 6.0).abs() < 0.5);
+    assert!((octave_db(-3.0) + 3.0).abs() < 0.5);
+  }
+
+  #[test]
+  fn zoom_needs_keys() {
+    let mut spectrogram = Spectrogram::new(9, 40.0, 60.0, 25.0);
+    spectrogram.set_frequency_range_hz(100.0, 1000.0).unwrap();
+    match spectrogram.zoom(40.0, 2.0) {
+      Err(Error::InvalidConfig { name, .. }) => assert_eq!(name, "scale"),
+      result => panic!("{:?}", result),
+    }
+    assert!(spectrogram.pan(1.0).is_err());
+  }
+
+  #[test]
+  fn range_ends_at_the_nyquist_frequency() {
+    let mut spectrogram = SpectrogramBuilder::new()
+      .decimation(Decimation::None)
+      .frequency_range(FrequencyScale::LinearHz, 100.0, 20000.0)
+      .build().unwrap();
+    
+    spectrogram.process_planar(&[0.0; 128], None, 48000.0).unwrap();
+    assert_eq!(spectrogram.take_clamped_range(), None);
+    assert_eq!(spectrogram.frequency_range(), (100.0, 20000.0));
+    
+    spectrogram.process_planar(&[0.0; 128], None, 16000.0).unwrap();
+    assert_eq!(spectrogram.take_clamped_range(), Some((100.0, 20000.0)));
+    assert_eq!(spectrogram.take_clamped_range(), None);
+    assert_eq!(spectrogram.frequency_range(), (100.0, 8000.0));
+    
+    spectrogram.set_frequency_range_hz(6000.0, 8000.0).unwrap();
+    spectrogram.process_planar(&[0.0; 128], None, 8000.0).unwrap();
+    assert_eq!(spectrogram.take_clamped_range(), Some((6000.0, 8000.0)));
+    assert_eq!(spectrogram.frequency_range(), (2000.0, 4000.0));
+  }
+
+  // Magnitude of a click in the middle of the first of `buffers` buffers,
+  // the others silent.
+  fn click(accumulate: Accumulate, buffers: usize) -> f32 {
+    let mut spectrogram = SpectrogramBuilder::new()
+      .buffer_size(1024)
+      .decimation(Decimation::None)
+      .hop(1024)
+      .accumulate(accumulate)
+      .build().unwrap();
+    
+    let mut samples = vec![0.0; buffers * 1024];
+    samples[512] = 1.0;
+    spectrogram.process_planar(&samples, None, 48000.0).unwrap();
+    spectrogram.frame().unwrap().magnitude(100)
+  }
+
+  #[test]
+  fn peak_hold_keeps_a_click() {
+    let alone = click(Accumulate::Average, 1);
+    let held = click(Accumulate::PeakHold, 64);
+    let averaged = click(Accumulate::Average, 64);
+    
+    assert!(alone > 0.0);
+    assert!((held - alone).abs() < 1e-6 * alone, "{} {}", held, alone);
+    let error = (averaged - alone / 64.0).abs();
+    assert!(error < 1e-6 * alone, "{} {}", averaged, alone);
+  }
+
+  #[test]
+  fn mel_range_in_hz() {
+    let mut spectrogram = SpectrogramBuilder::new()
+      .frequency_range(FrequencyScale::Mel, 100.0, 8000.0)
+      .build().unwrap();
+    spectrogram.process_planar(&[0.0; 128], None, 48000.0).unwrap();
+    assert_eq!(spectrogram.take_clamped_range(), None);
+    
+    assert!(spectrogram.position_at(100.0).abs() < 1e-6);
+    assert!((spectrogram.position_at(8000.0) - 1.0).abs() < 1e-6);
+    let middle = from_mel((to_mel(100.0) + to_mel(8000.0)) / 2.0);
+    assert!((spectrogram.frequency_at(0.5) - middle).abs() < 0.1);
+    assert!((spectrogram.position_at(middle) - 0.5).abs() < 1e-5);
+  }
+
+  #[test]
+  fn monotonic_on_every_scale() {
+    let scales = [
+      (FrequencyScale::PianoKey, 1.0, 88.0),
+      (FrequencyScale::Mel, 0.0, 20000.0),
+      (FrequencyScale::LinearHz, 0.0, 20000.0),
+      (FrequencyScale::LogHz, 20.0, 20000.0),
+      (FrequencyScale::Bark, 20.0, 20000.0),
+    ];
+    
+    for &(scale, from, to) in scales.iter() {
+      let spectrogram = SpectrogramBuilder::new()
+        .frequency_range(scale, from, to)
+        .build().unwrap();
+      
+      let mut last = (std::f32::NEG_INFINITY, std::f32::NEG_INFINITY);
+      for i in 0 ..= 1000 {
+        let hz = spectrogram.frequency_at(i as f32 / 1000.0);
+        let x = spectrogram.position_at(hz);
+        assert!(hz > last.0 && x > last.1, "{:?} {} {}", scale, hz, x);
+        last = (hz, x);
+      }
+    }
+  }
+
+  #[test]
+  fn chroma_alpha() {
+    let mut spectrogram = SpectrogramBuilder::new()
+      .buffer_size(8192)
+      .decimation(Decimation::None)
+      .window(Window::Hann)
+      .alpha(Alpha::Intensity)
+      .build().unwrap();
+    spectrogram.process_planar(&sine(440.0, 8192), None, 48000.0).unwrap();
+    
+    let mut rgba = vec![0; 4 * 12];
+    spectrogram.draw_chroma( Line {
+      len: 12,
+      data: &mut LineData::new(&mut rgba, PixelFormat::Rgba8),
+    }).unwrap();
+    
+    // A is the 10th pitch class from C.
+    let alpha = rgba.chunks(4).map(|p| p[3]).collect::<Vec<_>>();
+    assert_eq!(alpha[9], u8::max_value(), "{:?}", alpha);
+    assert!(alpha[3] < alpha[9] / 4, "{:?}", alpha);
+  }
+
+  #[test]
+  fn setters_keep_the_accumulator() {
+    let mut spectrogram = SpectrogramBuilder::new().build().unwrap();
+    spectrogram.process_planar(&sine(440.0, 4096), None, 48000.0).unwrap();
+    let (n, sum) = (spectrogram.freq_n, spectrogram.freq_sum.clone());
+    assert!(n > 0);
+    
+    spectrogram.set_boost(40.0).unwrap();
+    spectrogram.set_key_range(30.0, 70.0).unwrap();
+    assert_eq!(spectrogram.freq_n, n);
+    assert_eq!(spectrogram.freq_sum, sum);
+    assert!(spectrogram.frame().is_some());
+  }
+
+  // Frequency and magnitude of the largest bin.
+  fn peak_of(frame: &Frame) -> (f32,f32) {
+    frame.iter().fold((0.0, 0.0), |peak,bin| {
+      if bin.1 > peak.1 { bin } else { peak }
+    })
+  }
+
+  // Spectrogram of 2048 points after `decimation` of a sine of `hz` at
+  // 48 kHz.
+  fn decimated(decimation: Decimation, hz: f32) -> Spectrogram {
+    let mut spectrogram = SpectrogramBuilder::new()
+      .buffer_size(2048)
+      .decimation(decimation)
+      .window(Window::Hann)
+      .hop(2048)
+      .build().unwrap();
+    let len = 2048 * decimation.factor() + decimation.delay();
+    spectrogram.process_planar(&sine(hz, len), None, 48000.0).unwrap();
+    spectrogram
+  }
+
+  #[test]
+  fn decimation_attenuates_rather_than_aliases() {
+    let hz = 0.3 * 48000.0;
+    
+    let full = decimated(Decimation::None, hz);
+    let (peak_hz, peak) = peak_of(&full.frame().unwrap());
+    assert!((peak_hz - hz).abs() <= 48000.0 / 2048.0, "{}", peak_hz);
+    
+    // 14.4 kHz would fold over to 9.6 kHz at 24 kHz.
+    let half = decimated(Decimation::Half, hz);
+    let (_, alias) = peak_of(&half.frame().unwrap());
+    assert!(alias < 0.1 * peak, "{} {}", alias, peak);
+  }
+
+  #[test]
+  fn sixteenth_resolves_55_hz() {
+    let spectrogram = decimated(Decimation::Sixteenth, 55.0);
+    let frame = spectrogram.frame().unwrap();
+    let peak = (0 .. frame.len())
+      .max_by(|&a,&b| frame.magnitude(a).partial_cmp(&frame.magnitude(b)).unwrap())
+      .unwrap();
+    
+    // Bins of 3000 / 2048 Hz from the first one above DC.
+    let expected = 55.0 / (3000.0 / 2048.0) - 1.0;
+    assert!((peak as f32 - expected).abs() <= 1.0, "{} {}", peak, expected);
+    let hz = spectrogram.bin_to_hz(peak);
+    assert!((hz - 55.0).abs() <= 3000.0 / 2048.0, "{}", hz);
+  }
+
+  // Last row drawn of a second of a tone falling from 0.5 to 0.05 two
+  // chunks before the end, drawn after every `every` chunks of 800
+  // samples.
+  fn drawn_every(every: usize, window_ms: Option<f32>) -> Vec<u8> {
+    let mut spectrogram = SpectrogramBuilder::new()
+      .window(Window::Hann)
+      .hop(128)
+      .build().unwrap();
+    spectrogram.set_averaging_window_ms(window_ms).unwrap();
+    
+    let mut samples = sine(440.0, 48000);
+    for x in samples[48000 - 2 * 800 ..].iter_mut() {
+      *x *= 0.1;
+    }
+    
+    let mut rgba = vec![0; 4 * 100];
+    for (i,chunk) in samples.chunks(800).enumerate() {
+      spectrogram.process_planar(chunk, None, 48000.0).unwrap();
+      if (i + 1) % every == 0 {
+        spectrogram.draw_into_rgba(&mut rgba, 100).unwrap();
+      }
+    }
+    rgba
+  }
+
+  #[test]
+  fn any_draw_cadence() {
+    let difference = |a: &[u8], b: &[u8]| {
+      a.iter().zip(b).map(|(&a,&b)| (a as i32 - b as i32).abs()).max().unwrap()
+    };
+    
+    let every = drawn_every(1, Some(100.0));
+    let dropped = drawn_every(4, Some(100.0));
+    assert!(difference(&every, &dropped) <= 2, "{:?} {:?}", every, dropped);
+    
+    // Without a window the frame is the average since the last draw.
+    let every = drawn_every(1, None);
+    let dropped = drawn_every(4, None);
+    assert!(difference(&every, &dropped) > 20);
+  }
+
+  #[test]
+  fn peek_keeps_the_frame() {
+    let mut spectrogram = SpectrogramBuilder::new().build().unwrap();
+    spectrogram.process_planar(&sine(440.0, 4096), None, 48000.0).unwrap();
+    
+    let first = peek(&spectrogram, 50);
+    assert!(first.iter().any(|&x| x != 0));
+    assert_eq!(peek(&spectrogram, 50), first);
+    
+    // Nothing to draw, the line is left as is.
+    spectrogram.reset_accumulator();
+    let mut rgba = vec![7; 4 * 50];
+    spectrogram.draw_frame( Line {
+      len: 50,
+      data: &mut LineData::new(&mut rgba, PixelFormat::Rgba8),
+    }).unwrap();
+    assert!(rgba.iter().all(|&x| x == 7));
+    assert!(peek(&spectrogram, 50).iter().all(|&x| x == 0));
+  }
+
+  fn features_of(samples: &[f32], from_hz: f32, to_hz: f32) -> Features {
+    let mut spectrogram = SpectrogramBuilder::new()
+      .buffer_size(4096)
+      .decimation(Decimation::None)
+      .window(Window::Hann)
+      .hop(1024)
+      .frequency_range(FrequencyScale::LinearHz, from_hz, to_hz)
+      .build().unwrap();
+    spectrogram.process_planar(samples, None, 48000.0).unwrap();
+    spectrogram.features().unwrap()
+  }
+
+  #[test]
+  fn sine_and_noise_features() {
+    let sine = features_of(&sine(1000.0, 8192), 500.0, 1500.0);
+    assert!((sine.centroid - 1000.0).abs() < 48000.0 / 4096.0, "{:?}", sine);
+    assert!(sine.flatness < 0.1, "{:?}", sine);
+    
+    let mut state = 7;
+    let noise = (0 .. 96000)
+      .map(|_| (xorshift(&mut state) >> 40) as f32 / (1 << 24) as f32 - 0.5)
+      .collect::<Vec<_>>();
+    let noise = features_of(&noise, 100.0, 20000.0);
+    assert!(noise.flatness > 0.9 && noise.flatness <= 1.0, "{:?}", noise);
+    assert!((noise.centroid - 10050.0).abs() < 500.0, "{:?}", noise);
+  }
+
+  // Harmonics `from` to `to` of `hz`, each at 1/h of the amplitude.
+  fn harmonic_series(hz: f32, from: usize, to: usize) -> Vec<f32> {
+    (0 .. 32768).map(|i| {
+      (from ..= to).map(|h| {
+        let phase = 2.0 * PI * (h as f32 * hz) * i as f32 / 48000.0;
+        phase.sin() / h as f32
+      }).sum()
+    }).collect()
+  }
+
+  #[test]
+  fn pitch_of_harmonics() {
+    // The last two without their fundamental.
+    let cases = [(220.0, 1, 37), (329.63, 1, 44), (110.0, 2, 25),
+                 (146.83, 2, 30)];
+    for &(hz, from, key) in cases.iter() {
+      let mut spectrogram = SpectrogramBuilder::new()
+        .buffer_size(4096)
+        .hop(2048)
+        .window(Window::Hann)
+        .key_range(16.0, 64.0)
+        .build().unwrap();
+      let samples = harmonic_series(hz, from, 6);
+      spectrogram.process_planar(&samples, None, 48000.0).unwrap();
+      
+      let pitch = spectrogram.pitch().unwrap();
+      assert_eq!(pitch.key, key, "{} {:?}", hz, pitch);
+      assert!(pitch.cents.abs() < 10.0, "{} {:?}", hz, pitch);
+      assert!(pitch.confidence > 0.2, "{} {:?}", hz, pitch);
+    }
+  }
+
+  #[test]
+  fn no_pitch_in_silence() {
+    let mut spectrogram = SpectrogramBuilder::new().build().unwrap();
+    spectrogram.process_planar(&[0.0; 4096], None, 48000.0).unwrap();
+    assert!(spectrogram.pitch().is_none());
+  }
+
+  #[test]
+  fn brightness_contrast_invert() {
+    // Shades of silence and of a saturated bin, by hand.
+    let cases = [
+      ( 0.0, 1.0, false,   0, 255),
+      ( 0.2, 1.0, false,  51, 255),
+      (-0.2, 1.0, false,   0, 204),
+      ( 0.0, 0.5, false,  64, 191),
+      ( 0.0, 2.0, false,   0, 255),
+      ( 0.0, 0.0, false, 128, 128),
+      ( 0.0, 1.0, true,  255,   0),
+      ( 0.2, 0.5, true,  140,  13),
+    ];
+    
+    let mut silent = SpectrogramBuilder::new()
+      .color_map(ColorMap::Grayscale)
+      .build().unwrap();
+    silent.process_planar(&[0.0; 4096], None, 48000.0).unwrap();
+    let mut loud = SpectrogramBuilder::new()
+      .color_map(ColorMap::Grayscale)
+      .boost(1e6)
+      .build().unwrap();
+    loud.process_planar(&sine(440.0, 4096), None, 48000.0).unwrap();
+    
+    for &(brightness, contrast, invert, low, high) in cases.iter() {
+      for spectrogram in [&mut silent, &mut loud].iter_mut() {
+        spectrogram.set_brightness(brightness).unwrap();
+        spectrogram.set_contrast(contrast).unwrap();
+        spectrogram.set_invert(invert);
+      }
+      
+      let case = (brightness, contrast, invert);
+      let rgba = peek(&silent, 50);
+      assert!(rgba.chunks(4).all(|p| p[.. 3] == [low; 3]), "{:?}", case);
+      // The bins around the tone saturate.
+      let rgba = peek(&loud, 50);
+      assert!(rgba.chunks(4).any(|p| p[.. 3] == [high; 3]), "{:?}", case);
+    }
+  }
+
+  #[test]
+  fn mid_side_of_hard_left() {
+    let magnitudes = |mode| {
+      let mut spectrogram = SpectrogramBuilder::new()
+        .channel_mode(mode)
+        .build().unwrap();
+      let left = sine(440.0, 4096);
+      let right = vec![0.0; 4096];
+      spectrogram.process_planar(&left, Some(&right), 48000.0).unwrap();
+      let frame = spectrogram.frame().unwrap();
+      (0 .. frame.len()).map(|bin| frame.magnitude(bin)).collect::<Vec<_>>()
+    };
+    
+    // Mid and side both get half of the left channel.
+    let left = magnitudes(ChannelMode::Left);
+    let mid = magnitudes(ChannelMode::Mid);
+    let side = magnitudes(ChannelMode::Side);
+    let peak = left.iter().cloned().fold(0.0, f32::max);
+    assert!(peak > 0.01);
+    for bin in 0 .. left.len() {
+      assert_eq!(mid[bin], side[bin], "{}", bin);
+      assert!((mid[bin] - left[bin] / 2.0).abs() <= 1e-4 * peak, "{}", bin);
+    }
+  }
+
+  #[test]
+  fn boost_curves_increase() {
+    let curves = [
+      BoostCurve::Rational(0.0),
+      BoostCurve::Rational(25.0),
+      BoostCurve::Gamma(2.2),
+      BoostCurve::Gamma(0.5),
+      BoostCurve::Log { floor: 1e-3 },
+      BoostCurve::Sigmoid { midpoint: 0.3, steepness: 12.0 },
+    ];
+    
+    for &curve in curves.iter() {
+      curve.check().unwrap();
+      assert!(curve.apply(0.0).abs() < 1e-6, "{:?}", curve);
+      assert!((curve.apply(1.0) - 1.0).abs() < 1e-6, "{:?}", curve);
+      assert_eq!(curve.apply(-1.0), curve.apply(0.0));
+      assert_eq!(curve.apply(3.0), curve.apply(1.0));
+      
+      let grid = (0 ..= 1000)
+        .map(|i| curve.apply(i as f32 / 1000.0))
+        .collect::<Vec<_>>();
+      assert!(grid.windows(2).all(|w| w[1] >= w[0]), "{:?}", curve);
+    }
+  }
+
+  #[test]
+  fn recovers_from_nan() {
+    let new = || {
+      SpectrogramBuilder::new()
+        .color_map(ColorMap::Grayscale)
+        .build().unwrap()
+    };
+    let draw = |spectrogram: &mut Spectrogram| {
+      let mut rgba = vec![0; 4 * 50];
+      spectrogram.draw_frame( Line {
+        len: 50,
+        data: &mut LineData::new(&mut rgba, PixelFormat::Rgba8),
+      }).unwrap();
+      rgba
+    };
+    
+    // The NaN is silenced, as if it were a zero.
+    let mut broken = new();
+    let mut samples = sine(440.0, 4096);
+    samples[100] = std::f32::NAN;
+    let summary = broken.process_planar(&samples, None, 48000.0).unwrap();
+    assert_eq!(summary.non_finite, 1);
+    assert!(broken.freq_sum.iter().all(|x| x.is_finite()));
+    
+    let mut reference = new();
+    samples[100] = 0.0;
+    reference.process_planar(&samples, None, 48000.0).unwrap();
+    assert_eq!(draw(&mut broken), draw(&mut reference));
+    
+    let clean = sine(440.0, 8192);
+    broken.process_planar(&clean, None, 48000.0).unwrap();
+    reference.process_planar(&clean, None, 48000.0).unwrap();
+    let row = draw(&mut broken);
+    assert!(row.chunks(4).any(|p| p[0] > 50));
+    assert_eq!(row, draw(&mut reference));
+  }
+
+  #[test]
+  fn history_of_a_sweep() {
+    let mut spectrogram = SpectrogramBuilder::new()
+      .window(Window::Hann)
+      .hop(512)
+      .key_range(30.0, 80.0)
+      .build().unwrap();
+    assert_eq!(spectrogram.history().count(), 0);
+    assert!(spectrogram.enable_history(0).is_err());
+    spectrogram.enable_history(8).unwrap();
+    
+    // From 100 Hz up to 2100 Hz in a second, below key 80, drawn every
+    // 50 ms.
+    let mut phase = 0.0;
+    let samples = (0 .. 48000).map(|i| {
+      phase += 2.0 * PI * (100.0 + 2000.0 * i as f32 / 48000.0) / 48000.0;
+      0.5 * phase.sin()
+    }).collect::<Vec<_>>();
+    let mut rgba = vec![0; 4 * 10];
+    for chunk in samples.chunks(2400) {
+      spectrogram.process_planar(chunk, None, 48000.0).unwrap();
+      spectrogram.draw_frame( Line {
+        len: 10,
+        data: &mut LineData::new(&mut rgba, PixelFormat::Rgba8),
+      }).unwrap();
+    }
+    
+    let history = spectrogram.history().collect::<Vec<_>>();
+    assert_eq!(history.len(), 8);
+    assert!(history.iter().all(|frame| frame.len() == HISTORY_BINS));
+    let peaks = history.iter().map(|frame| {
+      (0 .. frame.len())
+        .max_by(|&a,&b| frame[a].partial_cmp(&frame[b]).unwrap())
+        .unwrap()
+    }).collect::<Vec<_>>();
+    assert!(peaks.windows(2).all(|w| w[1] > w[0]), "{:?}", peaks);
+    
+    spectrogram.enable_history(4).unwrap();
+    assert_eq!(spectrogram.history().count(), 4);
+    spectrogram.enable_history(16).unwrap();
+    assert_eq!(spectrogram.history().count(), 4);
+    spectrogram.disable_history();
+    assert_eq!(spectrogram.history().count(), 0);
+  }
+
+  #[test]
+  fn first_frame_of_real_input() {
+    let new = || {
+      SpectrogramBuilder::new()
+        .buffer_size(2048)
+        .decimation(Decimation::None)
+        .window(Window::Hann)
+        .hop(2048)
+        .build().unwrap()
+    };
+    let samples = sine(440.0, 2048);
+    
+    // Nothing until the buffer is full.
+    let mut fresh = new();
+    fresh.process_planar(&samples[.. 2047], None, 48000.0).unwrap();
+    assert!(fresh.frame().is_none());
+    assert_eq!(fresh.stats().ffts, 0);
+    fresh.process_planar(&samples[2047 ..], None, 48000.0).unwrap();
+    assert_eq!(fresh.stats().ffts, 1);
+    
+    // Same as a buffer of the same samples after other ones.
+    let mut state = 1;
+    let noise = (0 .. 2048)
+      .map(|_| (xorshift(&mut state) % 2000) as f32 / 1000.0 - 1.0)
+      .collect::<Vec<_>>();
+    let mut warm = new();
+    warm.process_planar(&noise, None, 48000.0).unwrap();
+    warm.reset_accumulator();
+    warm.process_planar(&samples, None, 48000.0).unwrap();
+    assert_eq!(warm.stats().ffts, 2);
+    
+    assert_eq!(fresh.freq_n, warm.freq_n);
+    assert_eq!(fresh.freq_sum, warm.freq_sum);
+    assert_eq!(peek(&fresh, 50), peek(&warm, 50));
+  }
+}
+
+
+#[cfg(all(test, feature = "serde"))]
+mod serde_tests {
+  use std::f32::consts::PI;
+
+  use super::*;
+
+  fn parse(json: &str) -> FrameData {
+    serde_json::from_str(json).unwrap()
+  }
+
+  #[test]
+  fn frame_data_round_trip() {
+    let mut spectrogram = Spectrogram::new(10, 40.0, 60.0, 25.0);
+    let empty = spectrogram.frame_data();
+    assert!(empty.bins.is_empty());
+    assert_eq!(parse(&empty.to_json()), empty);
+    
+    let samples = (0 .. 4096)
+      .map(|i| 0.5 * (2.0 * PI * 440.0 * i as f32 / 48000.0).sin())
+      .collect::<Vec<_>>();
+    spectrogram.process_planar(&samples, None, 48000.0).unwrap();
+    
+    let frame = spectrogram.frame_data();
+    assert!(!frame.bins.is_empty());
+    assert_eq!(parse(&spectrogram.frame_to_json()), frame);
+    assert_eq!(parse(&serde_json::to_string(&frame).unwrap()), frame);
+  }
+
+  #[test]
+  fn config_round_trip() {
+    let decibel = AmplitudeScale::Decibel { floor_db: -90.0, ceil_db: -6.0 };
+    let mut spectrogram = SpectrogramBuilder::new()
+      .buffer_size(1024)
+      .frequency_range(FrequencyScale::LogHz, 50.0, 8000.0)
+      .tuning_a4(432.0)
+      .amplitude_scale(decibel)
+      .window(Window::Hann)
+      .channel_mode(ChannelMode::Mix)
+      .color_map(ColorMap::Magma)
+      .build().unwrap();
+    
+    let config = spectrogram.config();
+    let json = serde_json::to_string(&config).unwrap();
+    let parsed = serde_json::from_str::<SpectrogramConfig>(&json).unwrap();
+    assert_eq!(parsed, config);
+    
+    let mut other = Spectrogram::new(9, 40.0, 60.0, 25.0);
+    other.apply_config(&parsed).unwrap();
+    assert_eq!(other.config(), config);
+    
+    spectrogram.apply_config(&SpectrogramConfig::default()).unwrap();
+    assert_eq!(spectrogram.config(), SpectrogramConfig::default());
+  }
 }
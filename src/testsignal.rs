@@ -0,0 +1,206 @@
+use std::f32::consts::PI;
+
+use crate::audio::BufferData;
+
+
+// Deterministic signal generators, continuous across successive calls so
+// that they can be streamed buffer after buffer.
+pub trait Signal {
+  fn next_sample(&mut self) -> f32;
+
+  fn fill(&mut self, out: &mut [f32]) {
+    for x in out.iter_mut() {
+      *x = self.next_sample();
+    }
+  }
+
+  // Writes the same signal to both channels.
+  fn fill_buffer(&mut self, data: &mut BufferData) {
+    for sample in data.iter_mut() {
+      let x = self.next_sample();
+      *sample.left = x;
+      if let Some(right) = sample.right {
+        *right = x;
+      }
+    }
+  }
+}
+
+pub struct Sine {
+  step: f32,
+  amplitude: f32,
+  phase: f32,
+}
+
+// Exponential sweep over `duration` seconds, holding `to_hz` afterwards.
+pub struct Sweep {
+  from_hz: f32,
+  to_hz: f32,
+  duration: f32,
+  sample_rate: f32,
+  elapsed: usize,
+  phase: f32,
+}
+
+// Uniform between -1 and 1, from a xorshift generator.
+pub struct WhiteNoise {
+  state: u64,
+}
+
+// Partials at multiples of the fundamental below the Nyquist frequency,
+// falling by `rolloff_db` per octave, normalized to a peak of at most 1.
+pub struct HarmonicSeries {
+  step: f32,
+  gains: Vec<f32>,
+  phase: f32,
+}
+
+
+impl Sine {
+  pub fn new(freq: f32, amplitude: f32, sample_rate: f32) -> Sine {
+    Sine {
+      step: freq / sample_rate,
+      amplitude: amplitude,
+      phase: 0.0,
+    }
+  }
+}
+
+impl Signal for Sine {
+  fn next_sample(&mut self) -> f32 {
+    let x = self.amplitude * (2.0 * PI * self.phase).sin();
+    self.phase = (self.phase + self.step).fract();
+    x
+  }
+}
+
+impl Sweep {
+  pub fn new( from_hz: f32,
+              to_hz: f32,
+              duration: f32,
+              sample_rate: f32 ) -> Sweep {
+    Sweep {
+      from_hz: from_hz,
+      to_hz: to_hz,
+      duration: duration,
+      sample_rate: sample_rate,
+      elapsed: 0,
+      phase: 0.0,
+    }
+  }
+
+  // Instantaneous frequency of the next sample.
+  pub fn frequency(&self) -> f32 {
+    let t = self.elapsed as f32 / self.sample_rate / self.duration;
+    self.from_hz * (self.to_hz / self.from_hz).powf(t.min(1.0))
+  }
+}
+
+impl Signal for Sweep {
+  fn next_sample(&mut self) -> f32 {
+    let x = (2.0 * PI * self.phase).sin();
+    self.phase = (self.phase + self.frequency() / self.sample_rate).fract();
+    self.elapsed += 1;
+    x
+  }
+}
+
+impl WhiteNoise {
+  pub fn new(seed: u64) -> WhiteNoise {
+    // Xorshift never leaves 0.
+    WhiteNoise {
+      state: seed.max(1),
+    }
+  }
+}
+
+impl Signal for WhiteNoise {
+  fn next_sample(&mut self) -> f32 {
+    self.state ^= self.state << 13;
+    self.state ^= self.state >> 7;
+    self.state ^= self.state << 17;
+
+    let unit = (self.state >> 40) as f32 / (1 << 24) as f32;
+    2.0 * unit - 1.0
+  }
+}
+
+impl HarmonicSeries {
+  pub fn new( fundamental: f32,
+              partials: usize,
+              rolloff_db: f32,
+              sample_rate: f32 ) -> HarmonicSeries {
+    let mut gains: Vec<f32> = (1 ..= partials)
+      .take_while(|&h| h as f32 * fundamental < sample_rate / 2.0)
+      .map(|h| 10.0_f32.powf(-rolloff_db * (h as f32).log2() / 20.0))
+      .collect();
+
+    let sum: f32 = gains.iter().sum();
+    if sum > 0.0 {
+      for gain in gains.iter_mut() {
+        *gain /= sum;
+      }
+    }
+
+    HarmonicSeries {
+      step: fundamental / sample_rate,
+      gains: gains,
+      phase: 0.0,
+    }
+  }
+}
+
+impl Signal for HarmonicSeries {
+  fn next_sample(&mut self) -> f32 {
+    let phase = self.phase;
+    let x = self.gains.iter().enumerate()
+      .map(|(i,gain)| gain * (2.0 * PI * (i + 1) as f32 * phase).sin())
+      .sum();
+    self.phase = (self.phase + self.step).fract();
+    x
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use crate::colormap::ColorMap;
+  use crate::spectrogram::FrequencyScale;
+  use crate::spectrogram::SpectrogramBuilder;
+  use crate::window::Window;
+
+  use super::*;
+
+  // Brightest pixel of a grayscale row.
+  fn peak_x(row: &[u8]) -> usize {
+    (0 .. row.len() / 4).max_by_key(|&x| row[4 * x]).unwrap()
+  }
+
+  #[test]
+  fn sweep_peak_moves_up() {
+    let mut spectrogram = SpectrogramBuilder::new()
+      .buffer_size(2048)
+      .frequency_range(FrequencyScale::LogHz, 50.0, 8000.0)
+      .window(Window::Hann)
+      .hop(512)
+      .color_map(ColorMap::Grayscale)
+      .build().unwrap();
+    
+    let mut sweep = Sweep::new(100.0, 4000.0, 2.0, 48000.0);
+    let mut samples = vec![0.0; 4096];
+    let mut row = vec![0; 4 * 200];
+    let mut peaks = Vec::new();
+    
+    for _ in 0 .. 2 * 48000 / samples.len() {
+      sweep.fill(&mut samples);
+      spectrogram.process_planar(&samples, None, 48000.0).unwrap();
+      spectrogram.draw_into_rgba(&mut row, 200).unwrap();
+      peaks.push(peak_x(&row));
+    }
+    
+    // The first buffer only fills the FFT.
+    let peaks = &peaks[1 ..];
+    assert!(peaks.windows(2).all(|w| w[0] <= w[1]), "{:?}", peaks);
+    assert!(peaks[peaks.len() - 1] > peaks[0] + 100, "{:?}", peaks);
+  }
+}
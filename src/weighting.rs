@@ -0,0 +1,86 @@
+// Frequency weightings of sound level meters, from the analog filter
+// responses of IEC 61672, normalized to 0 dB at 1 kHz.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Weighting {
+  None,
+  A,
+  C,
+}
+
+
+impl Weighting {
+  // Linear gain at `hz`.
+  pub fn gain(self, hz: f32) -> f32 {
+    match self {
+      Weighting::None => 1.0,
+      Weighting::A => (a_response(hz as f64) / a_response(1000.0)) as f32,
+      Weighting::C => (c_response(hz as f64) / c_response(1000.0)) as f32,
+    }
+  }
+
+  // Gains of `len` bins centered on `bin_hz(bin)`, none without a
+  // weighting.
+  pub fn table(self, len: usize, bin_hz: impl Fn(usize) -> f32) -> Vec<f32> {
+    match self {
+      Weighting::None => Vec::new(),
+      _ => (0 .. len).map(|bin| self.gain(bin_hz(bin))).collect(),
+    }
+  }
+}
+
+impl Default for Weighting {
+  fn default() -> Weighting {
+    Weighting::None
+  }
+}
+
+// Linear gain at `hz` of a tilt of `db_per_octave`, 0 dB at `pivot_hz`.
+pub fn tilt_gain(hz: f32, pivot_hz: f32, db_per_octave: f32) -> f32 {
+  10.0_f32.powf(db_per_octave * (hz / pivot_hz).log2() / 20.0)
+}
+
+
+// Poles of the weighting filters, in Hz.
+const F1: f64 = 20.598_997;
+const F2: f64 = 107.652_65;
+const F3: f64 = 737.862_23;
+const F4: f64 = 12_194.217;
+
+fn a_response(hz: f64) -> f64 {
+  let f2 = hz * hz;
+  F4 * F4 * f2 * f2
+    / ( (f2 + F1 * F1)
+        * ((f2 + F2 * F2) * (f2 + F3 * F3)).sqrt()
+        * (f2 + F4 * F4) )
+}
+
+fn c_response(hz: f64) -> f64 {
+  let f2 = hz * hz;
+  F4 * F4 * f2 / ((f2 + F1 * F1) * (f2 + F4 * F4))
+}
+
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn db(weighting: Weighting, hz: f32) -> f32 {
+    20.0 * weighting.gain(hz).log10()
+  }
+
+  #[test]
+  fn reference_gains() {
+    assert!(db(Weighting::A, 1000.0).abs() < 0.2);
+    assert!((db(Weighting::A, 100.0) + 19.1).abs() < 0.3, "{}", db(Weighting::A, 100.0));
+    assert!((db(Weighting::A, 10000.0) + 2.5).abs() < 0.3);
+    
+    assert!(db(Weighting::C, 1000.0).abs() < 0.2);
+    assert!((db(Weighting::C, 100.0) + 0.3).abs() < 0.2);
+    assert!((db(Weighting::C, 31.5) + 3.0).abs() < 0.2);
+    
+    assert_eq!(Weighting::None.gain(5.0), 1.0);
+    assert!(Weighting::None.table(10, |bin| bin as f32).is_empty());
+    assert_eq!(Weighting::A.table(10, |bin| bin as f32).len(), 10);
+  }
+}
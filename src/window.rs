@@ -0,0 +1,58 @@
+use std::f32::consts::PI;
+
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Window {
+  Rect,
+  Hann,
+  Hamming,
+  Blackman,
+}
+
+
+impl Default for Window {
+  fn default() -> Window {
+    Window::Rect
+  }
+}
+
+impl Window {
+  // Coefficients are normalized to a mean of 1 so that a windowed tone
+  // keeps the same magnitude as with `Rect`.
+  pub fn coefficients(self, size: usize) -> Vec<f32> {
+    let mut coefficients = vec![0.0; size];
+    self.fill(&mut coefficients);
+    coefficients
+  }
+
+  // Same as `coefficients` without allocating.
+  pub fn fill(self, coefficients: &mut [f32]) {
+    let size = coefficients.len();
+    for (n,c) in coefficients.iter_mut().enumerate() {
+      *c = self.coefficient(n, size);
+    }
+
+    let mean = coefficients.iter().sum::<f32>() / size as f32;
+    if mean > 0.0 {
+      for c in coefficients.iter_mut() {
+        *c /= mean;
+      }
+    }
+  }
+
+  fn coefficient(self, n: usize, size: usize) -> f32 {
+    if size < 2 {
+      return 1.0;
+    }
+
+    let x = 2.0 * PI * n as f32 / (size - 1) as f32;
+
+    match self {
+      Window::Rect     => 1.0,
+      Window::Hann     => 0.5 - 0.5 * x.cos(),
+      Window::Hamming  => 0.54 - 0.46 * x.cos(),
+      Window::Blackman => 0.42 - 0.5 * x.cos() + 0.08 * (2.0 * x).cos(),
+    }
+  }
+}